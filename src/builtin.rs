@@ -0,0 +1,132 @@
+use std::ops::{Range, RangeInclusive};
+
+use crate::{Code, Config, NumberFormat, Rt, random::Rng, value::Value};
+
+/// Builtin functions and their accepted number of arguments
+pub(crate) const BUILTINS: &[(&str, RangeInclusive<usize>)] = &[
+    ("env", 1..=1),
+    ("input", 0..=0),
+    ("read_file", 1..=1),
+    ("write_file", 2..=2),
+    ("append_file", 2..=2),
+    ("exists", 1..=1),
+    ("now", 0..=0),
+    ("sleep", 1..=1),
+    ("random", 0..=0),
+    ("random_int", 2..=2),
+    ("fmt", 2..=2),
+];
+
+impl<'a, Cfg: Config> Rt<'a, Cfg> {
+    /// Is the identifier at the cursor followed by `(`
+    pub(crate) fn peek_call(&self) -> bool {
+        self.peek() == "("
+    }
+
+    /// Next line from [`Config::read_line`] without the trailing newline
+    pub(crate) fn read_line(&mut self) -> Option<String> {
+        let mut line = self.cfg.read_line()?;
+        if line.ends_with('\n') {
+            line.pop();
+            if line.ends_with('\r') {
+                line.pop();
+            }
+        }
+        Some(line)
+    }
+
+    /// Parse `name(args..)`, arguments are checked even when not executed
+    pub(crate) fn call(&mut self) -> Value {
+        let at = self.i;
+        let name = self.tok();
+        self.bump(name);
+        self.bump("(");
+        let mut args = vec![];
+        while self.tok() != ")" {
+            args.push(self.atom_and_mixed(1));
+            if self.tok() != ")" {
+                self.expect_and_bump(",", "`,` or `)`");
+            }
+        }
+        let end = self.i + 1;
+        self.bump(")");
+
+        let Some((_, arity)) = BUILTINS.iter().find(|(builtin, _)| *builtin == name) else {
+            let msg = format!("Unknown function `{name}`");
+            self.error_at(Code::UnknownFunction, at..at+name.len(), &msg);
+        };
+        if !arity.contains(&args.len()) {
+            let expected = match (arity.start(), arity.end()) {
+                (1, 1) => "1 argument".to_owned(),
+                (min, max) if min == max => format!("{min} arguments"),
+                (min, max) => format!("{min} to {max} arguments"),
+            };
+            let msg = format!("Function `{name}` takes {expected}, but {} given", args.len());
+            self.error_at(Code::ArgumentCount, at..end, &msg);
+        }
+        if !self.effect() {
+            return Value::Null;
+        }
+
+        let span = at..end;
+        let mut args = args.into_iter();
+        let mut arg = || args.next().unwrap_or(Value::Null);
+        match name {
+            "env" => self.cfg.env(&arg().str()).map_or(Value::Null, Value::String),
+            "input" => self.read_line().map_or(Value::Null, Value::String),
+            "read_file" => {
+                let result = self.cfg.read_file(&arg().str());
+                Value::String(self.host(span, result))
+            },
+            "write_file" => {
+                let result = self.cfg.write_file(&arg().str(), &arg().str());
+                self.host(span, result);
+                Value::Null
+            },
+            "append_file" => {
+                let result = self.cfg.append_file(&arg().str(), &arg().str());
+                self.host(span, result);
+                Value::Null
+            },
+            "exists" => {
+                let result = self.cfg.exists(&arg().str());
+                self.host(span, result).into()
+            },
+            "now" => Value::Number(self.cfg.now()),
+            "sleep" => {
+                let seconds = arg().num(0);
+                self.cfg.sleep(if seconds > 0.0 { seconds } else { 0.0 });
+                Value::Null
+            },
+            "random" => Value::Number(self.rng().next_f64()),
+            "random_int" => {
+                let (lo, hi) = (arg().num(0).ceil(), arg().num(0).floor());
+                if !(lo <= hi && hi - lo < 2f64.powi(53)) {
+                    let msg = format!("Invalid range {lo} to {hi} for `random_int`");
+                    self.error_at(Code::InvalidArgument, span, &msg);
+                }
+                let n = self.rng().below_or_eq((hi - lo) as u64);
+                Value::Number(lo + n as f64)
+            },
+            "fmt" => {
+                let value = arg();
+                let result = NumberFormat::parse(&arg().str())
+                    .and_then(|format| format.value(&value));
+                match result {
+                    Ok(s) => Value::String(s),
+                    Err(e) => self.error_at(Code::InvalidArgument, span, &e),
+                }
+            },
+            _ => unreachable!("builtin `{name}` not implemented"),
+        }
+    }
+
+    fn rng(&mut self) -> &mut Rng {
+        self.rng.get_or_insert_with(|| Rng::new(self.cfg.seed()))
+    }
+
+    /// Unwrap the result of a host operation, or report it as a runtime error
+    fn host<T>(&self, span: Range<usize>, result: Result<T, String>) -> T {
+        result.unwrap_or_else(|e| self.error_at(Code::FileAccess, span, &e))
+    }
+}
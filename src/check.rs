@@ -0,0 +1,101 @@
+use std::collections::HashSet;
+
+use crate::{Code, Config, LineMode, Rt};
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Check<'a> {
+    assigns: Vec<(&'a str, usize)>,
+    reads: HashSet<&'a str>,
+    /// Assigned on every path, variables existed before the check are not included
+    defined: HashSet<&'a str>,
+    /// Assigned on any path
+    maybe: HashSet<&'a str>,
+    quiet: u32,
+}
+
+impl<'a, Cfg: Config> Rt<'a, Cfg> {
+    /// Parse the rest of the program without executing it,
+    /// lints are reported through [`Config::warn`]
+    pub fn check(&mut self) {
+        let mark = self.i;
+        self.check = Some(Check::default());
+        self.ignore();
+        self.proc();
+        self.ognore();
+
+        let mut check = self.check.take().unwrap();
+        if self.line_mode == Some(LineMode::Print) {
+            // printed after each line
+            check.reads.insert("line");
+        }
+        for (name, at) in check.assigns {
+            if !check.reads.contains(name) && !name.starts_with('_') {
+                self.warn_at(
+                    Code::UnusedVariable,
+                    at..at+name.len(),
+                    &format!("Variable `{name}` is assigned but never read"),
+                );
+            }
+        }
+        self.i = mark;
+    }
+
+    pub(crate) fn checking(&self) -> bool {
+        self.check.is_some()
+    }
+
+    pub(crate) fn check_read(&mut self, name: &'a str) {
+        let predefined = self.vars.contains_key(name);
+        let Some(check) = &mut self.check else { return };
+        check.reads.insert(name);
+        if check.quiet != 0 || predefined || check.defined.contains(name) {
+            return;
+        }
+        let span = self.i..self.i+name.len();
+        // `argN` beyond `argc` is only known at runtime
+        let arg = name.strip_prefix("arg").is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()));
+        if arg || check.maybe.contains(name) {
+            let msg = format!("Variable `{name}` may be used before assignment");
+            self.warn_at(Code::MaybeUnassigned, span, &msg);
+        } else {
+            // may be skipped by short-circuit operators, the runtime reports `E0004`
+            let msg = format!("Variable `{name}` is never assigned");
+            self.warn_at(Code::UnassignedVariable, span, &msg);
+        }
+    }
+
+    pub(crate) fn check_assign(&mut self, name: &'a str, at: usize) {
+        let Some(check) = &mut self.check else { return };
+        check.defined.insert(name);
+        check.maybe.insert(name);
+        if !check.assigns.iter().any(|&(assigned, _)| assigned == name) {
+            check.assigns.push((name, at));
+        }
+    }
+
+    /// Run a branch which may not be taken,
+    /// its assignments are not definitely assigned after it
+    pub(crate) fn check_branch(&mut self, f: impl FnOnce(&mut Self)) {
+        let saved = self.check.as_ref().map(|check| check.defined.clone());
+        f(self);
+        if let Some(check) = &mut self.check {
+            check.defined = saved.unwrap();
+        }
+    }
+
+    /// Run a loop body, when checking, walk it quietly once at first,
+    /// so reads can see assignments from the previous iterations
+    pub(crate) fn check_loop(&mut self, f: impl Fn(&mut Self)) {
+        if !self.checking() {
+            return f(self);
+        }
+        let mark = self.mark();
+        self.check_branch(|this| {
+            this.check.as_mut().unwrap().quiet += 1;
+            f(this);
+            this.check.as_mut().unwrap().quiet -= 1;
+        });
+        self.back(mark);
+        self.check_branch(f);
+    }
+}
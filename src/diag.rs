@@ -0,0 +1,157 @@
+use std::{fmt, ops::Range};
+
+use crate::json::Json;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+macro_rules! codes {
+    ($($name:ident $code:literal,)+) => {
+        /// Stable error (`E`) and warning (`W`) codes, explained by `--explain`
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum Code {
+            $($name,)+
+        }
+
+        impl Code {
+            pub const ALL: &[Code] = &[$(Code::$name),+];
+
+            pub fn as_str(&self) -> &'static str {
+                match self {
+                    $(Code::$name => $code,)+
+                }
+            }
+
+            /// Long-form explanation with examples
+            pub fn explain(&self) -> &'static str {
+                match self {
+                    $(Code::$name => include_str!(concat!("explain/", $code, ".md")),)+
+                }
+            }
+
+            pub fn parse(s: &str) -> Option<Self> {
+                Self::ALL.iter().copied().find(|code| code.as_str() == s)
+            }
+        }
+    };
+}
+
+codes! {
+    InvalidInput        "E0001",
+    UnterminatedString  "E0002",
+    InvalidEscape       "E0003",
+    UnknownVariable     "E0004",
+    InvalidNumber       "E0005",
+    ExpectedSemicolon   "E0006",
+    ExpectedToken       "E0007",
+    ExpectedCommand     "E0008",
+    InvalidOperator     "E0009",
+    InvalidExpression   "E0010",
+    MixedTypes          "E0011",
+    UnknownFunction     "E0012",
+    ArgumentCount       "E0013",
+    FileAccess          "E0014",
+    InvalidArgument     "E0015",
+    InvalidExitCode     "E0016",
+    UnusedVariable      "W0001",
+    ConstantCondition   "W0002",
+    MixedComparison     "W0003",
+    StringCoercion      "W0004",
+    MaybeUnassigned     "W0005",
+    UnassignedVariable  "W0006",
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pos {
+    pub byte: usize,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl Pos {
+    pub fn new(src: &str, byte: usize) -> Self {
+        let (line, column) = line_column::line_column(src, byte);
+        Self { byte, line, column }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Option<Code>,
+    pub message: String,
+    pub start: Pos,
+    pub end: Pos,
+    pub preview: String,
+}
+
+impl Diagnostic {
+    pub fn new(
+        severity: Severity,
+        code: impl Into<Option<Code>>,
+        src: &str,
+        span: Range<usize>,
+        message: impl Into<String>,
+    ) -> Self {
+        let preview = src[span.start..].lines().next().unwrap_or_default();
+        Self {
+            severity,
+            code: code.into(),
+            message: message.into(),
+            start: Pos::new(src, span.start),
+            end: Pos::new(src, span.end),
+            preview: preview.to_owned(),
+        }
+    }
+
+    /// Render as a single line JSON object, see README for the format
+    pub fn to_json(&self, file: &str) -> String {
+        Json::object([
+            ("severity", self.severity.as_str().into()),
+            ("code", self.code.map_or(Json::Null, |code| code.as_str().into())),
+            ("message", self.message.as_str().into()),
+            ("file", file.into()),
+            ("byte_start", self.start.byte.into()),
+            ("byte_end", self.end.byte.into()),
+            ("line_start", self.start.line.into()),
+            ("column_start", self.start.column.into()),
+            ("line_end", self.end.line.into()),
+            ("column_end", self.end.column.into()),
+            ("preview", self.preview.as_str().into()),
+        ]).to_string()
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self { severity, message, start, preview, .. } = self;
+        write!(f, "{}", severity.as_str())?;
+        if let Some(code) = self.code {
+            write!(f, "[{code}]")?;
+        }
+        write!(f, ": {message} at {}:{}", start.line, start.column)?;
+        if preview.is_empty() {
+            write!(f, " (EOF)")
+        } else {
+            write!(f, " `{preview}`")
+        }
+    }
+}
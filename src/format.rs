@@ -0,0 +1,170 @@
+use std::cmp::max;
+
+use crate::{Code, Diagnostic, KEYWORDS, Kind, Lexer, Severity, Token};
+
+const INDENT: &str = "    ";
+
+/// Format source, only fails on invalid tokens
+///
+/// - Indent 4 spaces inside blocks
+/// - One statement per line
+/// - Spaces around binary operators, except between operators written together like `/*`
+/// - Comments are preserved, and at most one blank line is kept
+pub fn format(src: &str) -> Result<String, Diagnostic> {
+    let mut f = Formatter {
+        buf: String::new(),
+        depth: 0,
+        newlines: 0,
+        continued: false,
+        line_start: true,
+        prev: None,
+        prev_unary: false,
+        spaced: false,
+    };
+    let mut lines = 0;
+    for token in Lexer::new(src).with_trivia(true) {
+        let token = token?;
+        match token.kind {
+            Kind::Whitespace => {
+                lines += token.text.matches('\n').count();
+                f.spaced = true;
+            },
+            Kind::Comment => {
+                f.comment(token.text.trim_end(), lines);
+                lines = 0;
+                f.spaced = true;
+            },
+            Kind::Unknown => {
+                let msg = "Invalid input";
+                return Err(Diagnostic::new(Severity::Error, Code::InvalidInput, src, token.span, msg));
+            },
+            _ => {
+                f.blank_lines(lines);
+                lines = 0;
+                f.token(token);
+            },
+        }
+    }
+    let mut buf = f.buf;
+    buf.truncate(buf.trim_end().len());
+    if !buf.is_empty() {
+        buf.push('\n');
+    }
+    Ok(buf)
+}
+
+struct Formatter<'a> {
+    buf: String,
+    depth: usize,
+    /// Newlines before the next token, 2 means keep a blank line
+    newlines: usize,
+    /// A statement broken by comments, indent one more level
+    continued: bool,
+    line_start: bool,
+    prev: Option<Token<'a>>,
+    prev_unary: bool,
+    /// Whitespace or a comment before the current token in the source
+    spaced: bool,
+}
+
+impl<'a> Formatter<'a> {
+    fn blank_lines(&mut self, lines: usize) {
+        if self.newlines != 0 && lines >= 2 {
+            self.newlines = 2;
+        }
+    }
+
+    fn comment(&mut self, comment: &str, lines: usize) {
+        if self.newlines == 0 && !self.buf.is_empty() {
+            self.continued = true;
+        }
+        if lines == 0 && !self.buf.is_empty() {
+            self.buf.push(' ');
+        } else {
+            self.blank_lines(lines);
+            self.newlines = max(self.newlines, 1);
+            self.flush(false);
+        }
+        self.buf.push_str(comment);
+        self.line_start = false;
+        self.newlines = max(self.newlines, 1);
+    }
+
+    fn flush(&mut self, close: bool) {
+        if self.newlines != 0 && !self.buf.is_empty() {
+            let blank = self.newlines >= 2 && !close && !self.buf.ends_with('{');
+            self.buf.push_str(if blank { "\n\n" } else { "\n" });
+            let depth = self.depth + usize::from(self.continued);
+            for _ in 0..depth {
+                self.buf.push_str(INDENT);
+            }
+            self.line_start = true;
+        }
+        self.newlines = 0;
+    }
+
+    fn token(&mut self, token: Token<'a>) {
+        let text = token.text;
+        match text {
+            "}" if self.buf.ends_with('{') => {
+                self.depth = self.depth.saturating_sub(1);
+                self.newlines = 0;
+            },
+            "}" => {
+                self.depth = self.depth.saturating_sub(1);
+                self.continued = false;
+                self.newlines = max(self.newlines, 1);
+                self.flush(true);
+            },
+            _ => {
+                self.flush(false);
+                if !self.line_start && self.space_before(&token) {
+                    self.buf.push(' ');
+                }
+            },
+        }
+        self.buf.push_str(text);
+        self.line_start = false;
+
+        match text {
+            "{" | "}" | ";" => {
+                if text == "{" {
+                    self.depth += 1;
+                }
+                self.continued = false;
+                self.newlines = 1;
+            },
+            _ => (),
+        }
+        self.prev_unary = matches!(text, "-" | "!")
+            && !self.prev.as_ref().is_some_and(operand_end);
+        self.prev = Some(token);
+        self.spaced = false;
+    }
+
+    fn space_before(&self, token: &Token<'_>) -> bool {
+        let Some(prev) = &self.prev else { return false };
+        match token.text {
+            ";" | ")" | "," => false,
+            // invalid like `/*`, keep it as written
+            _ if !self.spaced && operator(prev) && operator(token) && !matches!(token.text, "-" | "!") => false,
+            "-" if self.prev_unary && prev.text == "-" => true,
+            _ if prev.text == "(" || self.prev_unary => false,
+            "(" => prev.kind != Kind::Ident || KEYWORDS.contains(&prev.text),
+            _ => true,
+        }
+    }
+}
+
+fn operator(token: &Token<'_>) -> bool {
+    token.kind == Kind::Punct && !matches!(token.text, "(" | ")" | "{" | "}" | ";" | ",")
+}
+
+fn operand_end(token: &Token<'_>) -> bool {
+    match token.kind {
+        Kind::Number | Kind::StringLit => true,
+        Kind::Ident => !KEYWORDS.contains(&token.text),
+        Kind::Punct => token.text == ")",
+        Kind::Whitespace | Kind::Comment | Kind::Unknown => false,
+    }
+}
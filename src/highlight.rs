@@ -0,0 +1,131 @@
+use crate::{KEYWORDS, Kind, Lexer, Token, is_constant, lexer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// `<span>` with CSS classes, in a `<pre class="rsd">`
+    Html,
+    /// ANSI terminal colors
+    Ansi,
+}
+
+/// CSS classes of [`Format::Html`]
+pub const CLASSES: &[&str] = &[
+    "keyword", "number", "string", "escape", "operator", "punct", "comment", "error",
+];
+
+/// Highlight source, the rest of source after a lexing error is highlighted as `error`
+pub fn highlight(src: &str, format: Format) -> String {
+    let mut out = Output { buf: String::new(), format };
+    if format == Format::Html {
+        out.buf.push_str("<pre class=\"rsd\"><code>");
+    }
+    out.tokens(src);
+    if format == Format::Html {
+        out.buf.push_str("</code></pre>\n");
+    }
+    out.buf
+}
+
+fn class(token: &Token<'_>) -> Option<&'static str> {
+    Some(match token.kind {
+        Kind::Ident if KEYWORDS.contains(&token.text) || is_constant(token.text) => "keyword",
+        Kind::Ident | Kind::Whitespace => return None,
+        Kind::Number => "number",
+        Kind::StringLit => "string",
+        Kind::Punct if "{}()[];,".contains(token.text) => "punct",
+        Kind::Punct => "operator",
+        Kind::Comment => "comment",
+        Kind::Unknown => "error",
+    })
+}
+
+struct Output {
+    buf: String,
+    format: Format,
+}
+
+impl Output {
+    fn tokens(&mut self, src: &str) {
+        let mut end = 0;
+        for token in Lexer::new(src).with_trivia(true) {
+            match token {
+                Ok(token) if token.kind == Kind::StringLit && lexer::string_content(token.text).soft => {
+                    self.soft_string(token.text);
+                    end += token.text.len();
+                },
+                Ok(token) => {
+                    self.push(class(&token), token.text);
+                    end += token.text.len();
+                },
+                // the error may point inside the token, e.g. at an interpolation
+                Err(_) => self.push(Some("error"), &src[end..]),
+            }
+        }
+    }
+
+    /// Escapes and the braces of interpolations are `escape`, interpolated code is highlighted as usual
+    fn soft_string(&mut self, mut text: &str) {
+        let special = |text: &str| text.char_indices()
+            .find(|&(i, ch)| ch == '\\' || text[i..].starts_with("${"))
+            .map(|(i, _)| i);
+        while let Some(i) = special(text) {
+            self.push(Some("string"), &text[..i]);
+            let rest = &text[i..];
+            let len = if rest.starts_with('$') {
+                let len = lexer::interpolation_len(rest).expect("interpolation checked by lexer");
+                self.push(Some("escape"), "${");
+                self.tokens(&rest[2..len-1]);
+                self.push(Some("escape"), "}");
+                len
+            } else {
+                let len = lexer::escape(rest).0;
+                self.push(Some("escape"), &rest[..len]);
+                len
+            };
+            text = &rest[len..];
+        }
+        self.push(Some("string"), text);
+    }
+
+    fn push(&mut self, class: Option<&str>, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        match (self.format, class) {
+            (Format::Html, None) => self.html_escape(text),
+            (Format::Html, Some(class)) => {
+                self.buf.push_str("<span class=\"");
+                self.buf.push_str(class);
+                self.buf.push_str("\">");
+                self.html_escape(text);
+                self.buf.push_str("</span>");
+            },
+            (Format::Ansi, None) => self.buf.push_str(text),
+            (Format::Ansi, Some(class)) => {
+                let color = match class {
+                    "keyword" => "1;35",
+                    "number" => "36",
+                    "string" => "32",
+                    "escape" => "33",
+                    "operator" => "1",
+                    "comment" => "90",
+                    "error" => "4;31",
+                    _ => return self.buf.push_str(text),
+                };
+                self.buf.push_str(&format!("\x1b[{color}m{text}\x1b[0m"));
+            },
+        }
+    }
+
+    fn html_escape(&mut self, text: &str) {
+        for ch in text.chars() {
+            match ch {
+                '&' => self.buf.push_str("&amp;"),
+                '<' => self.buf.push_str("&lt;"),
+                '>' => self.buf.push_str("&gt;"),
+                '"' => self.buf.push_str("&quot;"),
+                _ => self.buf.push(ch),
+            }
+        }
+    }
+}
@@ -0,0 +1,280 @@
+use std::{fmt, ops::Index};
+
+/// Minimal JSON value, objects keep the insertion order
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub(crate) fn object<const N: usize>(fields: [(&str, Json); N]) -> Self {
+        Self::Object(fields.into_iter()
+            .map(|(key, value)| (key.to_owned(), value))
+            .collect())
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_u32(&self) -> Option<u32> {
+        match *self {
+            Json::Number(n) if n >= 0.0 && n <= f64::from(u32::MAX) => Some(n as u32),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_array(&self) -> &[Json] {
+        match self {
+            Json::Array(items) => items,
+            _ => &[],
+        }
+    }
+
+    pub(crate) fn parse(s: &str) -> Result<Self, String> {
+        let mut parser = Parser { s, i: 0 };
+        let value = parser.value()?;
+        parser.ws();
+        if parser.i != s.len() {
+            return Err(parser.error("Expected end of input"));
+        }
+        Ok(value)
+    }
+}
+
+static NULL: Json = Json::Null;
+
+impl Index<&str> for Json {
+    type Output = Json;
+
+    /// Returns [`Json::Null`] for missing fields
+    fn index(&self, key: &str) -> &Json {
+        self.get(key).unwrap_or(&NULL)
+    }
+}
+
+macro_rules! impl_from {
+    ($($ty:ty => |$v:ident| $e:expr),+ $(,)?) => {
+        $(impl From<$ty> for Json {
+            fn from($v: $ty) -> Self {
+                $e
+            }
+        })+
+    };
+}
+impl_from! {
+    bool => |b| Json::Bool(b),
+    f64 => |n| Json::Number(n),
+    u32 => |n| Json::Number(n.into()),
+    i32 => |n| Json::Number(n.into()),
+    usize => |n| Json::Number(n as f64),
+    &str => |s| Json::String(s.to_owned()),
+    String => |s| Json::String(s),
+    Vec<Json> => |items| Json::Array(items),
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(b) => write!(f, "{b}"),
+            Json::Number(n) if n.is_finite() => write!(f, "{n}"),
+            Json::Number(_) => f.write_str("null"),
+            Json::String(s) => {
+                let mut buf = String::new();
+                json_str(&mut buf, s);
+                f.write_str(&buf)
+            },
+            Json::Array(items) => {
+                f.write_str("[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i != 0 { f.write_str(",")? }
+                    write!(f, "{item}")?;
+                }
+                f.write_str("]")
+            },
+            Json::Object(fields) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i != 0 { f.write_str(",")? }
+                    write!(f, "{}:{value}", Json::String(key.clone()))?;
+                }
+                f.write_str("}")
+            },
+        }
+    }
+}
+
+pub(crate) fn json_str(buf: &mut String, s: &str) {
+    use fmt::Write;
+    buf.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            '\n' => buf.push_str("\\n"),
+            '\r' => buf.push_str("\\r"),
+            '\t' => buf.push_str("\\t"),
+            '\0'..='\x1f' => write!(buf, "\\u{:04x}", u32::from(ch)).unwrap(),
+            _ => buf.push(ch),
+        }
+    }
+    buf.push('"');
+}
+
+struct Parser<'a> {
+    s: &'a str,
+    i: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, msg: &str) -> String {
+        format!("{msg} at byte {}", self.i)
+    }
+
+    fn rest(&self) -> &str {
+        &self.s[self.i..]
+    }
+
+    fn ws(&mut self) {
+        let rest = self.rest();
+        self.i += rest.len() - rest.trim_start_matches([' ', '\t', '\r', '\n']).len();
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        let eaten = self.rest().starts_with(s);
+        if eaten {
+            self.i += s.len();
+        }
+        eaten
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.ws();
+        let next = self.rest().chars().next();
+        let value = match next {
+            Some('{') => {
+                self.i += 1;
+                let mut fields = vec![];
+                self.ws();
+                if !self.eat("}") {
+                    loop {
+                        self.ws();
+                        let key = self.string()?;
+                        self.ws();
+                        if !self.eat(":") {
+                            return Err(self.error("Expected `:`"));
+                        }
+                        fields.push((key, self.value()?));
+                        self.ws();
+                        if self.eat("}") { break }
+                        if !self.eat(",") {
+                            return Err(self.error("Expected `,` or `}`"));
+                        }
+                    }
+                }
+                Json::Object(fields)
+            },
+            Some('[') => {
+                self.i += 1;
+                let mut items = vec![];
+                self.ws();
+                if !self.eat("]") {
+                    loop {
+                        items.push(self.value()?);
+                        self.ws();
+                        if self.eat("]") { break }
+                        if !self.eat(",") {
+                            return Err(self.error("Expected `,` or `]`"));
+                        }
+                    }
+                }
+                Json::Array(items)
+            },
+            Some('"') => Json::String(self.string()?),
+            Some('-' | '0'..='9') => {
+                let len = self.rest()
+                    .find(|ch: char| !matches!(ch, '-' | '+' | '.' | 'e' | 'E' | '0'..='9'))
+                    .unwrap_or(self.rest().len());
+                let num = self.rest()[..len].parse()
+                    .map_err(|_| self.error("Invalid number"))?;
+                self.i += len;
+                Json::Number(num)
+            },
+            _ if self.eat("null") => Json::Null,
+            _ if self.eat("true") => Json::Bool(true),
+            _ if self.eat("false") => Json::Bool(false),
+            _ => return Err(self.error("Expected a value")),
+        };
+        Ok(value)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if !self.eat("\"") {
+            return Err(self.error("Expected a string"));
+        }
+        let mut buf = String::new();
+        loop {
+            let next = self.rest().chars().next();
+            let Some(ch) = next else {
+                return Err(self.error("String not terminated"));
+            };
+            self.i += ch.len_utf8();
+            match ch {
+                '"' => break Ok(buf),
+                '\\' => {
+                    let next = self.rest().chars().next();
+                    let Some(escape) = next else { continue };
+                    self.i += 1;
+                    buf.push(match escape {
+                        '"' | '\\' | '/' => escape,
+                        'b' => '\x08',
+                        'f' => '\x0c',
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'u' => self.unicode_escape()?,
+                        _ => return Err(self.error("Invalid escape")),
+                    });
+                },
+                _ => buf.push(ch),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let code = self.rest().get(..4)
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| self.error("Invalid unicode escape"))?;
+        self.i += 4;
+        Ok(code)
+    }
+
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex4()?;
+        let code = if (0xd800..0xdc00).contains(&high) && self.eat("\\u") {
+            let low = self.hex4()?;
+            0x10000 + ((high - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff)
+        } else {
+            high
+        };
+        Ok(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER))
+    }
+}
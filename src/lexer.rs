@@ -0,0 +1,437 @@
+use char_classes::any;
+use std::ops::Range;
+
+use crate::{Code, Diagnostic, Severity};
+use Kind::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Ident,
+    Number,
+    StringLit,
+    Punct,
+    /// Spaces, tabs and newlines, only yielded by [`Lexer::with_trivia`]
+    Whitespace,
+    /// `//` line comment without the newline, only yielded by [`Lexer::with_trivia`]
+    Comment,
+    Unknown,
+}
+
+impl Kind {
+    /// Returns `true` if the kind is [`Ident`].
+    ///
+    /// [`Ident`]: Kind::Ident
+    #[must_use]
+    pub(crate) fn is_ident(&self) -> bool {
+        matches!(self, Self::Ident)
+    }
+
+    /// Returns `true` if the kind is [`Whitespace`] or [`Comment`].
+    ///
+    /// [`Whitespace`]: Kind::Whitespace
+    /// [`Comment`]: Kind::Comment
+    #[must_use]
+    pub fn is_trivia(&self) -> bool {
+        matches!(self, Self::Whitespace | Self::Comment)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token<'a> {
+    pub kind: Kind,
+    pub text: &'a str,
+    pub span: Range<usize>,
+}
+
+trait StrExt {
+    fn next_boundary(&self, at: usize) -> &str;
+}
+
+impl StrExt for str {
+    #[track_caller]
+    fn next_boundary(&self, at: usize) -> &str {
+        let Some(ch) = self[at..].chars().next() else {
+            panic!("Cannot take next_boundary at {at} for {self}")
+        };
+        &self[..at+ch.len_utf8()]
+    }
+}
+
+/// Iterate tokens of source, trivia are skipped by default
+///
+/// Invalid characters are yielded as a single char [`Unknown`] token,
+/// after an error, the iteration is ended
+#[derive(Debug, Clone)]
+pub struct Lexer<'a> {
+    src: &'a str,
+    pub(crate) i: usize,
+    trivia: bool,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(src: &'a str) -> Self {
+        Self::new_at(src, 0)
+    }
+
+    pub(crate) fn new_at(src: &'a str, i: usize) -> Self {
+        Self { src, i, trivia: false }
+    }
+
+    /// Also yield [`Whitespace`] and [`Comment`] tokens
+    pub fn with_trivia(mut self, trivia: bool) -> Self {
+        self.trivia = trivia;
+        self
+    }
+
+    pub(crate) fn rest(&self) -> &'a str {
+        &self.src[self.i..]
+    }
+
+    pub(crate) fn skip_trivias(&mut self) {
+        loop {
+            self.i += self.mind(any!(" \t\r\n")).len();
+            if !self.rest().starts_with("//") { break }
+            self.i += self.mind(any!(^"\n")).len();
+        }
+    }
+
+    #[track_caller]
+    fn mind(&self, pred: fn(char) -> bool) -> &'a str {
+        let rest = self.rest();
+        rest.split_once(|ch| !pred(ch))
+            .map_or(rest, |it| it.0)
+    }
+
+    /// Kind of the token at the cursor, trivia are not classified
+    pub(crate) fn kind(&self) -> Kind {
+        let Some(ch) = self.rest().chars().next() else { return Unknown };
+        match ch {
+            any!(@"a-zA-Z_") => Ident,
+            any!(@"0-9") => Number,
+            '.' if self.rest()[1..].starts_with(any!("0-9")) => Number,
+            '<' if self.rest()[1..].strip_prefix('<').is_some_and(|it| it.starts_with(any!("a-zA-Z_'"))) => {
+                StringLit
+            },
+            any!(@"-+*/%<=>!&|{}()[];,") => Punct,
+            any!(@"'\"") => StringLit,
+            _ => Unknown,
+        }
+    }
+
+    /// Text of the token at the cursor, empty at [`Unknown`]
+    pub(crate) fn tok(&self) -> Result<&'a str, Diagnostic> {
+        Ok(match self.kind() {
+            Ident => self.ident(),
+            Punct => self.punct(),
+            Number => self.number(),
+            StringLit => self.string()?,
+            Whitespace | Comment | Unknown => "",
+        })
+    }
+
+    fn ident(&self) -> &'a str {
+        self.mind(any!("a-zA-Z0-9_"))
+    }
+
+    /// Digits, letters, `_` and `.`, and signs of decimal exponents, validated by [`parse_number`]
+    fn number(&self) -> &'a str {
+        let rest = self.rest();
+        let radix = rest.starts_with('0') && rest[1..].starts_with(any!("xXbB"));
+        let mut prev = '\0';
+        let len = rest.find(|ch: char| {
+            let sign = matches!(ch, '+' | '-') && matches!(prev, 'e' | 'E') && !radix;
+            prev = ch;
+            !(ch.is_ascii_alphanumeric() || matches!(ch, '_' | '.') || sign)
+        });
+        &rest[..len.unwrap_or(rest.len())]
+    }
+
+    fn string(&self) -> Result<&'a str, Diagnostic> {
+        let rest = self.rest();
+        if rest.starts_with("<<") {
+            return self.heredoc();
+        }
+        let triple = rest.starts_with("'''") || rest.starts_with(r#"""""#);
+        let quote = &rest[..if triple { 3 } else { 1 }];
+        let content = &rest[quote.len()..];
+        if quote.starts_with('"') {
+            let start = self.i + quote.len();
+            if let Some(end) = self.soft_end(start..self.src.len(), Some(quote))? {
+                return Ok(&self.src[self.i..end+quote.len()]);
+            }
+        } else if let Some(term) = content.find(quote) {
+            return Ok(&rest[..term+2*quote.len()]);
+        }
+        let line = self.mind(any!(^"\r\n"));
+        Err(self.error(Code::UnterminatedString, line.len(), "String literal not terminated"))
+    }
+
+    /// Position of `quote` in soft string content, skipping escapes and interpolations
+    fn soft_end(&self, content: Range<usize>, quote: Option<&str>) -> Result<Option<usize>, Diagnostic> {
+        let text = &self.src[content.clone()];
+        let mut i = 0;
+        while let Some(ch) = text[i..].chars().next() {
+            match ch {
+                '"' if quote.is_some_and(|quote| text[i..].starts_with(quote)) => {
+                    return Ok(Some(content.start + i));
+                },
+                '\\' => i += 1 + text[i+1..].chars().next().map_or(0, char::len_utf8),
+                '$' if text[i..].starts_with("${") => {
+                    let Some(len) = interpolation_len(&text[i..]) else {
+                        let at = content.start + i;
+                        let line = Lexer::new_at(self.src, at).mind(any!(^"\r\n"));
+                        return Err(Diagnostic::new(Severity::Error, Code::UnterminatedString,
+                            self.src, at..at+line.len(), "String interpolation not terminated"));
+                    };
+                    i += len;
+                },
+                _ => i += ch.len_utf8(),
+            }
+        }
+        Ok(None)
+    }
+
+    /// `<<EOF` or raw `<<'EOF'` and a newline, until a line starting with the marker
+    fn heredoc(&self) -> Result<&'a str, Diagnostic> {
+        let rest = self.rest();
+        let raw = rest[2..].starts_with('\'');
+        let marker = Lexer::new_at(rest, 2 + usize::from(raw)).ident();
+        let head_len = 2 + marker.len() + 2*usize::from(raw);
+        let closed = rest.is_char_boundary(head_len) && (!raw || rest.as_bytes()[head_len-1] == b'\'');
+        if marker.is_empty() || !closed {
+            let msg = "Unterminated heredoc header, expected a marker like `<<EOF` or `<<'EOF'`";
+            return Err(self.error(Code::UnterminatedString, 2 + usize::from(raw) + marker.len(), msg));
+        }
+        let (head, after) = rest.split_at(head_len);
+        let Some(body) = after.strip_prefix('\n').or_else(|| after.strip_prefix("\r\n")) else {
+            return Err(self.error(Code::UnterminatedString, head_len, &format!("Expected a newline after `{head}`")));
+        };
+        let mut at = rest.len() - body.len();
+        for line in body.split_inclusive('\n') {
+            let indent = line.len() - line.trim_start_matches([' ', '\t']).len();
+            let terminator = line[indent..].strip_prefix(marker)
+                .is_some_and(|after| !after.starts_with(any!("a-zA-Z0-9_")));
+            if terminator {
+                if !raw {
+                    self.soft_end(self.i+head_len..self.i+at, None)?;
+                }
+                return Ok(&rest[..at+indent+marker.len()]);
+            }
+            at += line.len();
+        }
+        let msg = format!("Heredoc `{head}` not terminated by a line of `{marker}`");
+        Err(self.error(Code::UnterminatedString, head_len, &msg))
+    }
+
+    fn punct(&self) -> &'a str {
+        const DOUBLE_OPS: [&str; 6] = ["&&", "||", "<=", ">=", "==", "!="];
+        let rest = self.rest();
+        let double = DOUBLE_OPS.iter().any(|op| rest.starts_with(op));
+        rest.next_boundary(double.into())
+    }
+
+    fn error(&self, code: Code, len: usize, msg: &str) -> Diagnostic {
+        Diagnostic::new(Severity::Error, code, self.src, self.i..self.i+len, msg)
+    }
+}
+
+/// Value of a [`Number`] token, errors have the span of the malformed part in the token
+///
+/// Decimal `1_000.5e-3` or `.5`, hexadecimal `0xFF` and binary `0b1010`,
+/// `_` is only allowed between digits
+pub(crate) fn parse_number(tok: &str) -> Result<f64, (Range<usize>, String)> {
+    let (radix, name) = match tok.get(..2) {
+        Some("0x" | "0X") => (16, "hexadecimal"),
+        Some("0b" | "0B") => (2, "binary"),
+        _ => (10, "decimal"),
+    };
+    let start = if radix == 10 { 0 } else { 2 };
+    let mut buf = String::with_capacity(tok.len());
+    let mut dot = None;
+    let mut exp = None;
+    let mut prev_digit = false;
+    for (i, ch) in tok[start..].char_indices() {
+        let i = start + i;
+        let span = i..i+ch.len_utf8();
+        let digit = ch.is_digit(radix);
+        match ch {
+            _ if digit => (),
+            '_' if prev_digit && tok[i+1..].starts_with(|ch: char| ch.is_digit(radix)) => continue,
+            '_' => return Err((span, "Misplaced `_` in number, only allowed between digits".into())),
+            '.' if radix == 10 && dot.is_none() && exp.is_none() => dot = Some(i),
+            '.' if exp.is_some() => return Err((span, "Unexpected `.` in exponent".into())),
+            '.' if dot.is_some() => return Err((span, "Unexpected second `.` in number".into())),
+            'e' | 'E' if radix == 10 && exp.is_none() && !buf.is_empty() => exp = Some(i),
+            '+' | '-' if exp.is_some_and(|exp| exp + 1 == i) => (),
+            _ => return Err((span, format!("Invalid character `{ch}` in {name} number"))),
+        }
+        prev_digit = digit;
+        buf.push(ch);
+    }
+    if let Some(exp) = exp && !prev_digit {
+        return Err((exp..tok.len(), "Expected digits in exponent".into()));
+    }
+    if radix == 10 {
+        return buf.parse().map_err(|e| (0..tok.len(), format!("Invalid number ({e})")));
+    }
+    if buf.is_empty() {
+        return Err((0..tok.len(), format!("Expected digits after `{}`", &tok[..2])));
+    }
+    Ok(buf.chars().fold(0.0, |n, d| n * f64::from(radix) + f64::from(d.to_digit(radix).unwrap())))
+}
+
+/// Content of a [`StringLit`] token
+pub(crate) struct StringContent<'a> {
+    /// Byte offset of the text in the token
+    pub offset: usize,
+    pub text: &'a str,
+    /// Indentation stripped from the start of each line
+    pub indent: usize,
+    /// Has escapes and interpolations
+    pub soft: bool,
+}
+
+/// Strip quotes or heredoc markers, multi-line strings also strip the first and last newline,
+/// and the common indentation of non-blank lines
+pub(crate) fn string_content(tok: &str) -> StringContent<'_> {
+    let common_indent = |text: &str| text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start_matches([' ', '\t']).len())
+        .min()
+        .unwrap_or(0);
+    if let Some(head) = tok.strip_prefix("<<") {
+        let offset = tok.find('\n').unwrap() + 1;
+        let body = &tok[offset..];
+        let text = body.rfind('\n').map_or("", |end| &body[..end]);
+        let text = text.strip_suffix('\r').unwrap_or(text);
+        let soft = !head.starts_with('\'');
+        return StringContent { offset, text, indent: common_indent(text), soft };
+    }
+    let soft = tok.starts_with('"');
+    if !(tok.starts_with("'''") || tok.starts_with(r#"""""#)) {
+        return StringContent { offset: 1, text: &tok[1..tok.len()-1], indent: 0, soft };
+    }
+    let inner = &tok[3..tok.len()-3];
+    let first_line = inner.find('\n').map(|end| &inner[..=end]);
+    let Some(first_line) = first_line.filter(|line| line.trim().is_empty()) else {
+        return StringContent { offset: 3, text: inner, indent: 0, soft };
+    };
+    let text = &inner[first_line.len()..];
+    let text = match text.rfind('\n') {
+        Some(end) if text[end..].trim().is_empty() => text[..end].strip_suffix('\r').unwrap_or(&text[..end]),
+        None if text.trim().is_empty() => "",
+        _ => text,
+    };
+    StringContent { offset: 3 + first_line.len(), text, indent: common_indent(text), soft }
+}
+
+/// Length of the `${..}` interpolation at the start of `src`, including the braces
+pub(crate) fn interpolation_len(src: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    for token in Lexer::new_at(src, 2) {
+        let token = token.ok()?;
+        match token.text {
+            "{" => depth += 1,
+            "}" if depth == 0 => return Some(token.span.end),
+            "}" => depth -= 1,
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Decode the soft string escape at the start of `src`, which starts with `\\`
+///
+/// Returns the length of the escape, also on errors,
+/// and `None` for a line continuation, which swallows the newline and the following indentation
+pub(crate) fn escape(src: &str) -> (usize, Result<Option<char>, String>) {
+    let Some(ch) = src[1..].chars().next() else {
+        return (1, Err("Unterminated soft string escape".into()));
+    };
+    let simple = |len: usize, ch: char| (len, Ok(Some(ch)));
+    match ch {
+        'n' => simple(2, '\n'),
+        'r' => simple(2, '\r'),
+        't' => simple(2, '\t'),
+        '0' => simple(2, '\0'),
+        '"' | '\'' | '\\' | '$' => simple(2, ch),
+        '\n' | '\r' => {
+            let newline = if src[1..].starts_with("\r\n") { 2 } else { 1 };
+            let indent = Lexer::new_at(src, 1+newline).mind(any!(" \t")).len();
+            (1 + newline + indent, Ok(None))
+        },
+        'x' => {
+            let digits = Lexer::new_at(src, 2).mind(any!("0-9a-fA-F")).len().min(2);
+            let len = 2 + digits;
+            if digits != 2 {
+                return (len, Err("Hex escape must have two hex digits, like `\\x7F`".into()));
+            }
+            match u8::from_str_radix(&src[2..len], 16) {
+                Ok(n @ ..=0x7F) => simple(len, n.into()),
+                _ => (len, Err(format!("Hex escape `{}` out of range, must be at most `\\x7F`", &src[..len]))),
+            }
+        },
+        'u' => {
+            if !src[2..].starts_with('{') {
+                return (2, Err("Unicode escape must be braced, like `\\u{1F600}`".into()));
+            }
+            let digits = Lexer::new_at(src, 3).mind(any!("0-9a-fA-F")).len();
+            match src[3+digits..].chars().next() {
+                Some('}') => (),
+                Some(ch) => {
+                    let msg = format!("Invalid character `{ch}` in unicode escape");
+                    return (3 + digits + ch.len_utf8(), Err(msg));
+                },
+                None => return (3 + digits, Err("Unicode escape not terminated by `}`".into())),
+            }
+            let len = 4 + digits;
+            let escape = &src[..len];
+            if digits == 0 {
+                return (len, Err("Empty unicode escape".into()));
+            }
+            if digits > 6 {
+                return (len, Err(format!("Unicode escape `{escape}` has more than 6 hex digits")));
+            }
+            match u32::from_str_radix(&src[3..3+digits], 16).unwrap() {
+                0xD800..=0xDFFF => (len, Err(format!("Unicode escape `{escape}` is a surrogate"))),
+                n => match char::from_u32(n) {
+                    Some(ch) => simple(len, ch),
+                    None => (len, Err(format!("Unicode escape `{escape}` out of range, must be at most `\\u{{10FFFF}}`"))),
+                },
+            }
+        },
+        _ => (1 + ch.len_utf8(), Err(format!("Invalid soft string escape `\\{ch}`"))),
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<Token<'a>, Diagnostic>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.trivia {
+            self.skip_trivias();
+        }
+        let rest = self.rest();
+        let (kind, text) = if rest.is_empty() {
+            return None;
+        } else if rest.starts_with("//") {
+            (Comment, self.mind(any!(^"\n")))
+        } else if rest.starts_with(any!(" \t\r\n")) {
+            (Whitespace, self.mind(any!(" \t\r\n")))
+        } else {
+            match self.kind() {
+                Unknown => (Unknown, rest.next_boundary(0)),
+                kind => match self.tok() {
+                    Ok(text) => (kind, text),
+                    Err(e) => {
+                        self.i = self.src.len();
+                        return Some(Err(e));
+                    },
+                },
+            }
+        };
+        let span = self.i..self.i+text.len();
+        self.i = span.end;
+        Some(Ok(Token { kind, text, span }))
+    }
+}
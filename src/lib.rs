@@ -1,53 +1,143 @@
-use crate::value::{Cmp, Value};
+use crate::value::Cmp;
 use Kind::*;
-use char_classes::any;
-use std::{collections::HashMap, fmt, mem};
-
+use std::{borrow::Cow, collections::{HashMap, HashSet}, fmt, ops::Range, thread, time::{Duration, SystemTime}};
+
+pub use crate::diag::{Code, Diagnostic, Pos, Severity};
+pub use crate::lexer::{Kind, Lexer, Token};
+pub use crate::lines::LineMode;
+pub use crate::number::NumberFormat;
+pub use crate::value::Value;
+
+pub mod format;
+pub mod highlight;
+pub mod lsp;
+
+mod builtin;
+mod check;
+mod diag;
+mod json;
+mod lexer;
+mod lines;
+mod number;
+mod random;
 mod value;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum Kind {
-    Ident,
-    Number,
-    StringLit,
-    Punct,
-    Unknown,
+/// Words that start a statement or a command
+pub(crate) const KEYWORDS: &[&str] = &[
+    "if", "while", "print", "write", "eprint", "exit", "BEGIN", "END",
+];
+
+/// Predefined variables, highlighted as keywords
+pub(crate) const CONSTANTS: &[(&str, Value)] = &[
+    ("null", Value::Null),
+    ("inf", Value::Number(f64::INFINITY)),
+    ("nan", Value::Number(f64::NAN)),
+];
+
+pub(crate) fn is_constant(name: &str) -> bool {
+    CONSTANTS.iter().any(|&(constant, _)| constant == name)
 }
 
-impl Kind {
-    /// Returns `true` if the kind is [`Ident`].
-    ///
-    /// [`Ident`]: Kind::Ident
-    #[must_use]
-    fn is_ident(&self) -> bool {
-        matches!(self, Self::Ident)
+pub trait Config {
+    /// Text output of `write`, and of `print` by default
+    fn print(&mut self, args: fmt::Arguments<'_>);
+
+    /// Value of `print`, printed as a line with [`Config::number_format`] by default,
+    /// hosts can override it to handle typed values
+    fn emit(&mut self, value: &Value) {
+        let value = self.number_format().apply(value);
+        self.print(format_args!("{value}\n"))
     }
-}
 
-trait StrExt {
-    fn next_boundary(&self, at: usize) -> &str;
-}
+    /// Format of numbers in `print`, `write` and `eprint`
+    fn number_format(&self) -> NumberFormat {
+        NumberFormat::default()
+    }
 
-impl StrExt for str {
-    #[track_caller]
-    fn next_boundary(&self, at: usize) -> &str {
-        let Some(ch) = self[at..].chars().next() else {
-            panic!("Cannot take next_boundary at {at} for {self}")
-        };
-        &self[..at+ch.len_utf8()]
+    /// Diagnostic output of `eprint`, kept apart from the data of `print`, discarded by default
+    fn eprint(&mut self, _args: fmt::Arguments<'_>) {}
+
+    fn error(&self, diag: Diagnostic) -> ! {
+        panic!("{diag}")
+    }
+
+    fn warn(&mut self, _diag: Diagnostic) {}
+
+    /// Reject implicit coercions between different value types
+    fn strict(&self) -> bool {
+        false
+    }
+
+    /// Environment variable for `env(name)`, no variables are visible by default
+    fn env(&self, _name: &str) -> Option<String> {
+        None
+    }
+
+    /// Next line for `input()`, a trailing newline is allowed, `None` at EOF
+    fn read_line(&mut self) -> Option<String> {
+        None
+    }
+
+    /// File contents for `read_file(path)`, file access is denied by default,
+    /// an `Err` message is reported as a runtime error
+    fn read_file(&mut self, _path: &str) -> Result<String, String> {
+        Err("File access is not allowed".to_owned())
+    }
+
+    /// Create or truncate the file for `write_file(path, s)`
+    fn write_file(&mut self, _path: &str, _contents: &str) -> Result<(), String> {
+        Err("File access is not allowed".to_owned())
+    }
+
+    /// Create or append to the file for `append_file(path, s)`
+    fn append_file(&mut self, _path: &str, _contents: &str) -> Result<(), String> {
+        Err("File access is not allowed".to_owned())
+    }
+
+    /// Whether the file or directory exists for `exists(path)`
+    fn exists(&mut self, _path: &str) -> Result<bool, String> {
+        Err("File access is not allowed".to_owned())
+    }
+
+    /// Seconds since the Unix epoch for `now()`, the system clock by default
+    fn now(&mut self) -> f64 {
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0.0, |time| time.as_secs_f64())
+    }
+
+    /// Block for `sleep(seconds)`, never negative
+    fn sleep(&mut self, seconds: f64) {
+        thread::sleep(Duration::try_from_secs_f64(seconds).unwrap_or_default());
+    }
+
+    /// Seed of `random()`, requested once at the first use, derived from [`Config::now`] by default
+    fn seed(&mut self) -> u64 {
+        self.now().to_bits()
     }
 }
 
-pub trait Config {
-    fn print(&mut self, args: fmt::Arguments<'_>);
+/// How [`Rt::proc`] finished
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// Reached the end of the program
+    Done,
+    /// Stopped by `exit code;`
+    Exit(i32),
 }
 
 #[derive(Debug, Clone)]
 pub struct Rt<'a, Cfg> {
     src: &'a str,
     i: usize,
-    vars: HashMap<&'a str, Value>,
+    vars: HashMap<Cow<'a, str>, Value>,
     ignore_level: u32,
+    check: Option<check::Check<'a>>,
+    warned: HashSet<(Code, usize)>,
+    /// Set by `exit`, the rest of the program is ignored
+    exit: Option<i32>,
+    line_mode: Option<LineMode>,
+    rng: Option<random::Rng>,
     pub cfg: Cfg,
 }
 
@@ -57,12 +147,29 @@ impl<Cfg: Default> Default for Rt<'_, Cfg> {
     }
 }
 
-impl<Cfg: Config> Rt<'_, Cfg> {
-    pub fn proc(&mut self) {
+impl<'a, Cfg: Config> Rt<'a, Cfg> {
+    pub fn proc(&mut self) -> Status {
         self.skip_trivias();
+        match self.line_mode {
+            Some(mode) => self.proc_lines(mode),
+            None => self.stmts(false),
+        }
+        self.eof_or_error(Code::InvalidInput, "Invalid input");
+        match self.exit.take() {
+            Some(code) => {
+                self.ognore();
+                Status::Exit(code)
+            },
+            None => Status::Done,
+        }
+    }
+
+    /// Statements until EOF, or until an `END` block in line mode
+    fn stmts(&mut self, until_end: bool) {
         loop {
             match self.kind() {
-                Unknown => break self.eof_or_error("Invalid input"),
+                Unknown => break,
+                Ident if until_end && self.at_section("END") => break,
                 _ => self.stmt(),
             }
         }
@@ -82,7 +189,12 @@ impl<Cfg: Config> Rt<'_, Cfg> {
 
     fn expect_and_bump(&mut self, s: &str, msg: &str) {
         if self.tok() != s {
-            self.error(&format!("Expected a {msg}"));
+            let code = if s == ";" {
+                Code::ExpectedSemicolon
+            } else {
+                Code::ExpectedToken
+            };
+            self.error(code, &format!("Expected a {msg}"));
         }
         self.bump(s);
     }
@@ -91,13 +203,17 @@ impl<Cfg: Config> Rt<'_, Cfg> {
         self.bump("while");
         let mark = self.mark();
 
+        if self.checking() {
+            self.check_constant_condition();
+        }
+
         loop {
             let mut cond = false;
             self.expr(|_, v| cond = v.bool());
 
             if !cond {
                 self.ignore();
-                self.block();
+                self.check_loop(Self::block);
                 self.ognore();
                 return;
             }
@@ -115,7 +231,7 @@ impl<Cfg: Config> Rt<'_, Cfg> {
             this.ignore();
         });
 
-        self.block();
+        self.check_branch(Self::block);
 
         if !cond {
             self.ognore();
@@ -137,18 +253,49 @@ impl<Cfg: Config> Rt<'_, Cfg> {
         match self.tok() {
             "print" => {
                 self.bump("print");
+                self.expr(|this, v| this.cfg.emit(&v))
+            },
+            "write" => {
+                self.bump("write");
                 self.expr(|this, v| {
-                    this.cfg.print(format_args!("{v}\n"))
+                    let v = this.cfg.number_format().apply(&v);
+                    this.cfg.print(format_args!("{v}"))
                 })
             },
+            "eprint" => {
+                self.bump("eprint");
+                self.expr(|this, v| {
+                    let v = this.cfg.number_format().apply(&v);
+                    this.cfg.eprint(format_args!("{v}\n"))
+                })
+            },
+            "exit" => {
+                self.bump("exit");
+                let at = self.i;
+                self.expr(|this, v| {
+                    let code = v.num(0);
+                    if !(0.0..256.0).contains(&code) {
+                        let span = at..this.src[..this.i].trim_end().len();
+                        let msg = format!("Exit code {code} out of range 0 to 255");
+                        this.error_at(Code::InvalidExitCode, span, &msg);
+                    }
+                    this.exit = Some(code as i32);
+                    this.ignore();
+                })
+            },
+            _ if self.kind().is_ident() && self.peek_call() => {
+                self.call();
+            },
             var if self.kind().is_ident() => {
+                let at = self.i;
                 self.bump(var);
                 self.expect_and_bump("=", "`=`");
                 self.expr(|this, v| {
-                    this.vars.insert(var, v);
+                    this.vars.insert(var.into(), v);
                 });
+                self.check_assign(var, at);
             }
-            _ => self.error("Expected a command or assign"),
+            _ => self.error(Code::ExpectedCommand, "Expected a command or assign"),
         }
     }
 
@@ -162,21 +309,22 @@ impl<Cfg: Config> Rt<'_, Cfg> {
     fn atom_and_mixed(&mut self, min_bp: u32) -> Value {
         let mut value = self.atom_and_prefix(min_bp);
 
-        loop {
-            let Some((bp, rbp)) = self.prec() else { break };
+        while let Some((bp, rbp)) = self.prec() {
             if bp < min_bp { break }
             let tok = self.tok();
             macro_rules! infix {
                 ($($op:literal $method:ident $(($($t:tt)*))?,)+) => {
                     match tok {
                         $($op => {
+                            let op_at = self.i;
                             self.bump($op);
                             let rhs = self.atom_and_mixed(rbp);
                             if self.effect() {
+                                self.lint_op(op_at, $op, &value, &rhs);
                                 value.$method(rhs $(, $($t)*)?);
                             }
                         })+
-                        _ => self.error("Invalid operator")
+                        _ => self.error(Code::InvalidOperator, "Invalid operator")
                     }
                 };
             }
@@ -245,7 +393,22 @@ impl<Cfg: Config> Rt<'_, Cfg> {
     }
 
     fn atom(&mut self) -> Value {
+        if self.kind() == Ident && self.peek_call() {
+            return self.call();
+        }
         if !self.effect() {
+            match self.kind() {
+                Ident => self.check_read(self.tok()),
+                StringLit => {
+                    self.string(self.tok());
+                    return Value::Null;
+                },
+                Number => {
+                    self.number();
+                    return Value::Null;
+                },
+                kind => self.error(Code::InvalidExpression, &format!("Invalid expression {kind:?}")),
+            }
             self.bump_any(self.tok());
             return Value::Null;
         }
@@ -253,44 +416,97 @@ impl<Cfg: Config> Rt<'_, Cfg> {
             Ident => {
                 let name = self.tok();
                 let val = self.vars.get(name).cloned()
-                    .unwrap_or_else(|| self.error(&format!("Unknown variable `{name}`")));
+                    .unwrap_or_else(|| self.error(Code::UnknownVariable, &format!("Unknown variable `{name}`")));
                 self.bump(name);
                 val
             },
-            Number => {
-                let num = self.tok();
-                let val = num.parse().map(Value::Number)
-                    .unwrap_or_else(|e| self.error(&format!("Invalid number ({e})")));
-                self.bump(num);
-                val
-            },
-            StringLit => {
-                let tok = self.tok();
-                let content = &tok[1..tok.len()-1];
-                if tok.starts_with('"') {
-                    let mut escape = false;
-                    let mut buf = String::with_capacity(content.len());
-                    for ch in content.chars() {
-                        match ch {
-                            'n' if escape => buf.push('\n'),
-                            'r' if escape => buf.push('\r'),
-                            't' if escape => buf.push('\t'),
-                            '"' if escape => buf.push('"'),
-                            '\\' if escape => buf.push('\\'),
-                            '\\' => { escape = true; continue },
-                            _ if escape => self.error(&format!("Invalid soft string escape `\\{ch}`")),
-                            _ => buf.push(ch),
-                        }
-                        escape = false;
+            Number => self.number(),
+            StringLit => self.string(self.tok()),
+            kind => self.error(Code::InvalidExpression, &format!("Invalid expression {kind:?}")),
+        }
+    }
+
+    fn number(&mut self) -> Value {
+        let num = self.tok();
+        let val = lexer::parse_number(num).unwrap_or_else(|(span, msg)| {
+            self.error_at(Code::InvalidNumber, self.i+span.start..self.i+span.end, &msg)
+        });
+        self.bump(num);
+        Value::Number(val)
+    }
+
+    /// String literal, soft strings have escapes and `${expr}` interpolations, which are only evaluated in effect
+    fn string(&mut self, tok: &'a str) -> Value {
+        let start = self.i;
+        let content = lexer::string_content(tok);
+        let text = content.text;
+        let dedent = |i: usize| {
+            i + text[i..].bytes().take(content.indent).take_while(|b| matches!(b, b' ' | b'\t')).count()
+        };
+        let mut buf = String::with_capacity(text.len());
+        let mut i = dedent(0);
+        while let Some(ch) = text[i..].chars().next() {
+            let at = start + content.offset + i;
+            match ch {
+                '\\' if content.soft => {
+                    let (len, escape) = lexer::escape(&text[i..]);
+                    match escape {
+                        Ok(ch) => buf.extend(ch),
+                        Err(msg) => self.error_at(Code::InvalidEscape, at..at+len, &msg),
                     }
-                    self.bump(tok);
-                    Value::String(buf)
-                } else {
-                    self.bump(tok);
-                    Value::String(content.to_owned())
-                }
+                    i += len;
+                },
+                '$' if content.soft && text[i..].starts_with("${") => {
+                    let len = lexer::interpolation_len(&text[i..])
+                        .expect("interpolation checked by lexer");
+                    self.i = at + 2;
+                    self.skip_trivias();
+                    let value = self.atom_and_mixed(0);
+                    self.expect_and_bump("}", "`}` closing the interpolation");
+                    value.str_to(&mut buf);
+                    i += len;
+                },
+                '\n' => {
+                    buf.push(ch);
+                    i = dedent(i + 1);
+                },
+                _ => {
+                    buf.push(ch);
+                    i += ch.len_utf8();
+                },
             }
-            kind => self.error(&format!("Invalid expression {kind:?}")),
+        }
+        self.i = start;
+        self.bump(tok);
+        Value::String(buf)
+    }
+
+    fn lint_op(&mut self, op_at: usize, op: &str, lhs: &Value, rhs: &Value) {
+        if self.cfg.strict() && lhs.strict_rejects(op, rhs) {
+            let (lhs, rhs) = (lhs.type_name(), rhs.type_name());
+            let msg = format!("Operation `{op}` between {lhs} and {rhs} in strict mode");
+            self.error_at(Code::MixedTypes, op_at..op_at+op.len(), &msg);
+        }
+        let Some(code) = lhs.lossy_op(op, rhs) else { return };
+        let (lhs, rhs) = (lhs.type_name(), rhs.type_name());
+        let msg = match code {
+            Code::MixedComparison => format!("Comparison `{op}` between {lhs} and {rhs} \
+                                              is ordered by type"),
+            _ => format!("Operation `{op}` between {lhs} and {rhs} silently coerces"),
+        };
+        self.warn_at(code, op_at..op_at+op.len(), &msg);
+    }
+
+    fn check_constant_condition(&mut self) {
+        let mark = self.mark();
+        let tok = self.tok();
+        let constant = matches!(self.kind(), Number | StringLit) || is_constant(tok);
+        self.bump_any(tok);
+        let single = self.tok() == "{";
+        self.back(mark);
+        if constant && single {
+            let span = self.i..self.i+tok.len();
+            self.warn_at(Code::ConstantCondition, span, "Condition of `while` is a constant");
         }
     }
 
@@ -325,13 +541,19 @@ impl<Cfg: Config> Rt<'_, Cfg> {
 
 impl<'a, Cfg> Rt<'a, Cfg> {
     pub fn with_config(cfg: Cfg) -> Self {
-        let mut vars = HashMap::new();
-        vars.insert("null", Value::Null);
+        let vars = CONSTANTS.iter()
+            .map(|(name, value)| ((*name).into(), value.clone()))
+            .collect();
         Self {
             src: Default::default(),
             i: Default::default(),
             vars,
             ignore_level: Default::default(),
+            check: Default::default(),
+            warned: Default::default(),
+            exit: Default::default(),
+            line_mode: Default::default(),
+            rng: Default::default(),
             cfg,
         }
     }
@@ -340,6 +562,27 @@ impl<'a, Cfg> Rt<'a, Cfg> {
         self.src = src;
     }
 
+    /// Define or overwrite a variable, before or between executions
+    pub fn set_var(&mut self, name: impl Into<String>, value: Value) {
+        self.vars.insert(name.into().into(), value);
+    }
+
+    pub fn get_var(&self, name: &str) -> Option<&Value> {
+        self.vars.get(name)
+    }
+
+    /// Expose script arguments as `arg0`..`argN`, and their count as `argc`
+    pub fn set_args<S: Into<String>>(&mut self, args: impl IntoIterator<Item = S>) {
+        let mut argc = 0;
+        for (i, arg) in args.into_iter().enumerate() {
+            self.set_var(format!("arg{i}"), Value::String(arg.into()));
+            argc = i + 1;
+        }
+        self.set_var("argc", Value::Number(argc as f64));
+    }
+}
+
+impl<'a, Cfg: Config> Rt<'a, Cfg> {
     fn ignore(&mut self) {
         self.ignore_level += 1;
     }
@@ -353,116 +596,82 @@ impl<'a, Cfg> Rt<'a, Cfg> {
     }
 
     #[track_caller]
-    fn eof_or_error(&mut self, msg: &str) {
+    fn eof_or_error(&mut self, code: Code, msg: &str) {
         self.skip_trivias();
         if self.i == self.src.len() {
             return;
         }
-        self.error(msg);
+        self.error(code, msg);
     }
 
     #[track_caller]
-    fn error(&self, msg: &str) -> ! {
-        let (line, col) = line_column::line_column(self.src, self.i);
-        let preview = self.mind(any!(^"\r\n"));
-        if preview.is_empty() {
-            panic!("{msg} at {line}:{col} (EOF)")
-        } else {
-            panic!("{msg} at {line}:{col} `{preview}`")
-        }
-    }
-
-    fn tok(&self) -> &'a str {
-        match self.kind() {
-            Ident => self.ident(),
-            Punct => self.punct(),
-            Number => self.number(),
-            StringLit => self.string(),
-            Unknown => "",
-        }
-    }
-
-    fn rest(&self) -> &'a str {
-        &self.src[self.i..]
+    fn error(&self, code: Code, msg: &str) -> ! {
+        self.error_at(code, self.error_span(), msg)
     }
 
     #[track_caller]
-    fn bump(&mut self, s: &str) {
-        debug_assert_eq!(&self.rest()[..s.len()], s);
-        self.bump_any(s)
+    fn error_at(&self, code: Code, span: Range<usize>, msg: &str) -> ! {
+        let diag = Diagnostic::new(Severity::Error, code, self.src, span, msg);
+        self.cfg.error(diag)
     }
 
-    fn bump_any(&mut self, s: &str) {
-        self.i += s.len();
-        self.skip_trivias();
+    fn warn_at(&mut self, code: Code, span: Range<usize>, msg: &str) {
+        if !self.warned.insert((code, span.start)) {
+            return;
+        }
+        let diag = Diagnostic::new(Severity::Warning, code, self.src, span, msg);
+        self.cfg.warn(diag)
     }
 
-    fn skip_trivias(&mut self) {
-        loop {
-            self.i += self.mind(any!(" \t\r\n")).len();
-            if !self.rest().starts_with("//") { break }
-            self.i += self.mind(any!(^"\n")).len();
-        }
+    /// Like [`Self::tok`], but never error
+    fn error_span(&self) -> Range<usize> {
+        let len = match self.lexer().tok() {
+            Ok("") => self.rest().chars().next().map_or(0, char::len_utf8),
+            Ok(tok) => tok.len(),
+            Err(e) => e.end.byte - e.start.byte,
+        };
+        self.i..self.i+len
     }
 
-    #[track_caller]
-    fn mind(&self, pred: fn(char) -> bool) -> &'a str {
-        self.mind_at(0, pred)
+    fn lexer(&self) -> Lexer<'a> {
+        Lexer::new_at(self.src, self.i)
     }
 
-    #[track_caller]
-    fn mind_at(&self, at: usize, pred: fn(char) -> bool) -> &'a str {
-        let rest = &self.rest()[at..];
-        rest.split_once(|ch| !pred(ch))
-            .map_or(rest, |it| it.0)
+    fn tok(&self) -> &'a str {
+        self.lexer().tok().unwrap_or_else(|e| self.cfg.error(e))
     }
 
     fn kind(&self) -> Kind {
-        let Some(ch) = self.rest().chars().next() else { return Unknown };
-        match ch {
-            any!(@"a-zA-Z_") => Ident,
-            any!(@"0-9") => Number,
-            any!(@"-+*/%<=>!&|{}()[];") => Punct,
-            any!(@"'\"") => StringLit,
-            _ => Unknown,
-        }
+        self.lexer().kind()
     }
 
-    fn ident(&self) -> &'a str {
-        self.mind(any!("a-zA-Z0-9_"))
+    fn rest(&self) -> &'a str {
+        &self.src[self.i..]
     }
 
-    fn number(&self) -> &'a str {
-        self.mind(any!("0-9."))
+    /// The token after the current token, empty at EOF or errors
+    fn peek(&self) -> &'a str {
+        let mut lexer = self.lexer();
+        lexer.i += self.tok().len();
+        lexer.skip_trivias();
+        lexer.tok().unwrap_or_default()
     }
 
-    fn string(&self) -> &'a str {
-        let rest = self.rest();
-        if let Some(content) = rest.strip_prefix('"') {
-            let mut escape = false;
-            for (i, ch) in content.char_indices() {
-                if mem::take(&mut escape) {
-                    continue;
-                }
-                if ch == '\\' { escape = true }
-                if ch == '"' {
-                    return rest.next_boundary(i+1);
-                }
-            }
-            self.error("String literal not terminated")
-        } else {
-            let Some(term) = rest[1..].find('\'') else {
-                self.error("String literal not terminated")
-            };
-            rest.next_boundary(term+1)
-        }
+    #[track_caller]
+    fn bump(&mut self, s: &str) {
+        debug_assert_eq!(&self.rest()[..s.len()], s);
+        self.bump_any(s)
+    }
+
+    fn bump_any(&mut self, s: &str) {
+        self.i += s.len();
+        self.skip_trivias();
     }
 
-    fn punct(&self) -> &'a str {
-        const DOUBLE_OPS: [&str; 6] = ["&&", "||", "<=", ">=", "==", "!="];
-        let rest = self.rest();
-        let double = DOUBLE_OPS.iter().any(|op| rest.starts_with(op));
-        rest.next_boundary(double.into())
+    fn skip_trivias(&mut self) {
+        let mut lexer = self.lexer();
+        lexer.skip_trivias();
+        self.i = lexer.i;
     }
 }
 
@@ -477,7 +686,7 @@ mod mark {
         }
 
         pub(crate) fn back(&mut self, Mark(mark): Mark) {
-            debug_assert!(mark < self.i);
+            debug_assert!(mark <= self.i);
             self.i = mark;
         }
     }
@@ -0,0 +1,55 @@
+use crate::{Config, Rt, Value};
+
+/// Run the program once per input line, like `awk` or `perl -n`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineMode {
+    /// `-n`, only run the program
+    Each,
+    /// `-p`, also print `line` after each run
+    Print,
+}
+
+impl<'a, Cfg: Config> Rt<'a, Cfg> {
+    /// Bind each line from [`Config::read_line`] to `line` and its number to `nr`,
+    /// the optional `BEGIN { }` and `END { }` blocks run only once
+    pub fn set_line_mode(&mut self, mode: LineMode) {
+        self.line_mode = Some(mode);
+        self.set_var("line", Value::Null);
+        self.set_var("nr", Value::Number(0.0));
+    }
+
+    pub(crate) fn at_section(&self, name: &str) -> bool {
+        self.tok() == name && self.peek() == "{"
+    }
+
+    pub(crate) fn proc_lines(&mut self, mode: LineMode) {
+        if self.at_section("BEGIN") {
+            self.bump("BEGIN");
+            self.block();
+        }
+        let mark = self.mark();
+        let mut nr = 0;
+        while self.effect() && let Some(line) = self.read_line() {
+            nr += 1;
+            self.set_var("line", Value::String(line));
+            self.set_var("nr", Value::Number(f64::from(nr)));
+            self.stmts(true);
+            self.back(mark);
+            if self.exit.is_some() {
+                break;
+            }
+            if mode == LineMode::Print {
+                let line = self.get_var("line").cloned().unwrap_or(Value::Null);
+                self.cfg.emit(&line);
+            }
+        }
+        // also after `exit`, the body is skipped to reach `END`
+        self.ignore();
+        self.check_loop(|this| this.stmts(true));
+        self.ognore();
+        if self.at_section("END") {
+            self.bump("END");
+            self.block();
+        }
+    }
+}
@@ -0,0 +1,450 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, BufRead, Write},
+    mem,
+    ops::Range,
+    panic::{self, AssertUnwindSafe},
+};
+
+use crate::{Config, Diagnostic, KEYWORDS, Kind, Lexer, Rt, Severity, Token, is_constant, json::Json};
+
+const TOKEN_TYPES: &[&str] = &["keyword", "variable", "number", "string", "operator", "comment"];
+
+/// Serve Language Server Protocol over `input` and `output` until the `exit` notification
+///
+/// Supports diagnostics from [`Rt::check`], hover, go-to-definition,
+/// document symbols and semantic tokens
+pub fn serve(mut input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+    let mut server = Server::default();
+    while let Some(msg) = read_message(&mut input)? {
+        let replies = match Json::parse(&msg) {
+            Ok(msg) => server.handle(&msg),
+            Err(e) => vec![error_response(Json::Null, -32700, &e)],
+        };
+        for reply in replies {
+            write!(output, "Content-Length: {}\r\n\r\n", reply.len())?;
+            output.write_all(reply.as_bytes())?;
+        }
+        output.flush()?;
+        if server.exit {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut len = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            if len.is_some() { break }
+            continue;
+        }
+        if let Some((name, value)) = line.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            len = value.trim().parse().ok();
+        }
+    }
+    let mut buf = vec![0; len.unwrap()];
+    input.read_exact(&mut buf)?;
+    String::from_utf8(buf)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn response(id: Json, result: Json) -> String {
+    Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("id", id),
+        ("result", result),
+    ]).to_string()
+}
+
+fn error_response(id: Json, code: i32, msg: &str) -> String {
+    Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("id", id),
+        ("error", Json::object([
+            ("code", code.into()),
+            ("message", msg.into()),
+        ])),
+    ]).to_string()
+}
+
+fn notification(method: &str, params: Json) -> String {
+    Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("method", method.into()),
+        ("params", params),
+    ]).to_string()
+}
+
+#[derive(Debug, Default)]
+struct Server {
+    docs: HashMap<String, String>,
+    shutdown: bool,
+    exit: bool,
+}
+
+impl Server {
+    fn handle(&mut self, msg: &Json) -> Vec<String> {
+        let params = &msg["params"];
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default().to_owned();
+        let Some(id) = msg.get("id").cloned() else {
+            return self.notify(msg["method"].as_str().unwrap_or_default(), params, uri);
+        };
+        let doc = self.docs.get(&uri).map_or("", String::as_str);
+        let result = match msg["method"].as_str().unwrap_or_default() {
+            _ if self.shutdown => {
+                return vec![error_response(id, -32600, "Server is shutdown")];
+            },
+            "initialize" => capabilities(),
+            "shutdown" => {
+                self.shutdown = true;
+                Json::Null
+            },
+            "textDocument/hover" => {
+                let at = offset(doc, &params["position"]);
+                Analysis::new(doc).hover(doc, at)
+            },
+            "textDocument/definition" => {
+                let at = offset(doc, &params["position"]);
+                Analysis::new(doc).definition(doc, at, &uri)
+            },
+            "textDocument/documentSymbol" => Analysis::new(doc).symbols(doc),
+            "textDocument/semanticTokens/full" => semantic_tokens(doc),
+            method => {
+                return vec![error_response(id, -32601, &format!("Method not found: {method}"))];
+            },
+        };
+        vec![response(id, result)]
+    }
+
+    fn notify(&mut self, method: &str, params: &Json, uri: String) -> Vec<String> {
+        let text = match method {
+            "exit" => {
+                self.exit = true;
+                return vec![];
+            },
+            "textDocument/didOpen" => &params["textDocument"]["text"],
+            "textDocument/didChange" => {
+                let changes = params["contentChanges"].as_array();
+                changes.last().map_or(&Json::Null, |change| &change["text"])
+            },
+            "textDocument/didClose" => {
+                self.docs.remove(&uri);
+                return vec![publish_diagnostics(&uri, "")];
+            },
+            _ => return vec![],
+        };
+        let text = text.as_str().unwrap_or_default().to_owned();
+        let reply = publish_diagnostics(&uri, &text);
+        self.docs.insert(uri, text);
+        vec![reply]
+    }
+}
+
+fn capabilities() -> Json {
+    let token_types = TOKEN_TYPES.iter().map(|&ty| ty.into()).collect::<Vec<_>>();
+    Json::object([
+        ("capabilities", Json::object([
+            ("textDocumentSync", 1.into()),
+            ("hoverProvider", true.into()),
+            ("definitionProvider", true.into()),
+            ("documentSymbolProvider", true.into()),
+            ("semanticTokensProvider", Json::object([
+                ("legend", Json::object([
+                    ("tokenTypes", token_types.into()),
+                    ("tokenModifiers", vec![].into()),
+                ])),
+                ("full", true.into()),
+            ])),
+        ])),
+        ("serverInfo", Json::object([
+            ("name", env!("CARGO_PKG_NAME").into()),
+            ("version", env!("CARGO_PKG_VERSION").into()),
+        ])),
+    ])
+}
+
+/// Convert LSP position into byte offset, `character` is counted in UTF-16
+fn offset(src: &str, position: &Json) -> usize {
+    let line = position["line"].as_u32().unwrap_or_default();
+    let character = position["character"].as_u32().unwrap_or_default() as usize;
+    let line_start = src.split_inclusive('\n')
+        .take(line as usize)
+        .map(str::len)
+        .sum::<usize>();
+    let mut utf16 = 0;
+    src[line_start..].char_indices()
+        .find(|&(_, ch)| {
+            utf16 += ch.len_utf16();
+            ch == '\n' || utf16 > character
+        })
+        .map_or(src.len(), |(i, _)| line_start + i)
+}
+
+fn position(src: &str, offset: usize) -> (u32, u32) {
+    let before = &src[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i+1);
+    let line = before.matches('\n').count();
+    let character = before[line_start..].encode_utf16().count();
+    (line as u32, character as u32)
+}
+
+fn range(src: &str, span: Range<usize>) -> Json {
+    let pos = |offset| {
+        let (line, character) = position(src, offset);
+        Json::object([
+            ("line", line.into()),
+            ("character", character.into()),
+        ])
+    };
+    Json::object([
+        ("start", pos(span.start)),
+        ("end", pos(span.end)),
+    ])
+}
+
+#[derive(Debug, Default)]
+struct CheckCfg {
+    warnings: Vec<Diagnostic>,
+}
+
+impl Config for CheckCfg {
+    fn print(&mut self, _args: fmt::Arguments<'_>) {}
+
+    fn error(&self, diag: Diagnostic) -> ! {
+        panic::resume_unwind(Box::new(diag))
+    }
+
+    fn warn(&mut self, diag: Diagnostic) {
+        self.warnings.push(diag);
+    }
+}
+
+fn publish_diagnostics(uri: &str, src: &str) -> String {
+    let mut rt = Rt::with_config(CheckCfg::default());
+    rt.load_source(src);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| rt.check()));
+    let mut diags = mem::take(&mut rt.cfg.warnings);
+    if let Err(payload) = result {
+        match payload.downcast::<Diagnostic>() {
+            Ok(diag) => diags.push(*diag),
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+    let diags = diags.into_iter().map(|diag| {
+        let severity = match diag.severity {
+            Severity::Error => 1,
+            Severity::Warning => 2,
+        };
+        Json::object([
+            ("range", range(src, diag.start.byte..diag.end.byte)),
+            ("severity", severity.into()),
+            ("code", diag.code.map_or(Json::Null, |code| code.as_str().into())),
+            ("source", env!("CARGO_PKG_NAME").into()),
+            ("message", diag.message.into()),
+        ])
+    }).collect::<Vec<_>>();
+    notification("textDocument/publishDiagnostics", Json::object([
+        ("uri", uri.into()),
+        ("diagnostics", diags.into()),
+    ]))
+}
+
+fn semantic_tokens(src: &str) -> Json {
+    let mut data = vec![];
+    let (mut prev_line, mut prev_char) = (0, 0);
+    for token in Lexer::new(src).with_trivia(true).map_while(Result::ok) {
+        let ty = match token.kind {
+            Kind::Ident if KEYWORDS.contains(&token.text) || is_constant(token.text) => 0,
+            Kind::Ident => 1,
+            Kind::Number => 2,
+            Kind::StringLit => 3,
+            Kind::Punct if !"{}()[];,".contains(token.text) => 4,
+            Kind::Comment => 5,
+            _ => continue,
+        };
+        let mut start = token.span.start;
+        for line in token.text.split_inclusive('\n') {
+            let (line_no, character) = position(src, start);
+            let len = line.trim_end_matches(['\r', '\n']).encode_utf16().count();
+            start += line.len();
+            if len == 0 { continue }
+            let delta_char = if line_no == prev_line { character - prev_char } else { character };
+            data.extend([line_no - prev_line, delta_char, len as u32, ty, 0]);
+            (prev_line, prev_char) = (line_no, character);
+        }
+    }
+    Json::object([
+        ("data", data.into_iter().map(Json::from).collect::<Vec<_>>().into()),
+    ])
+}
+
+/// Variables from the assignments at statement start
+#[derive(Debug, Default)]
+struct Analysis<'a> {
+    tokens: Vec<Token<'a>>,
+    /// First assignment and the value kinds of all assignments
+    vars: Vec<(Token<'a>, ValueKinds)>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct ValueKinds {
+    number: bool,
+    string: bool,
+    null: bool,
+    unknown: bool,
+}
+
+impl ValueKinds {
+    const NUMBER: Self = Self { number: true, string: false, null: false, unknown: false };
+    const STRING: Self = Self { number: false, string: true, null: false, unknown: false };
+    const NULL: Self = Self { number: false, string: false, null: true, unknown: false };
+    const BOOL: Self = Self { number: true, string: false, null: true, unknown: false };
+    const UNKNOWN: Self = Self { number: false, string: false, null: false, unknown: true };
+
+    fn union(self, other: Self) -> Self {
+        Self {
+            number: self.number | other.number,
+            string: self.string | other.string,
+            null: self.null | other.null,
+            unknown: self.unknown | other.unknown,
+        }
+    }
+}
+
+impl fmt::Display for ValueKinds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kinds = [
+            (self.number, "number"),
+            (self.string, "string"),
+            (self.null, "null"),
+            (self.unknown, "unknown"),
+        ];
+        let names = kinds.iter()
+            .filter(|(has, _)| *has)
+            .map(|(_, name)| *name)
+            .collect::<Vec<_>>();
+        f.write_str(&names.join(" | "))
+    }
+}
+
+impl<'a> Analysis<'a> {
+    fn new(src: &'a str) -> Self {
+        let mut this = Self {
+            tokens: Lexer::new(src).map_while(Result::ok).collect(),
+            vars: vec![],
+        };
+        let mut i = 0;
+        while i < this.tokens.len() {
+            let stmt_start = i == 0 || matches!(this.tokens[i-1].text, ";" | "{" | "}");
+            let token = &this.tokens[i];
+            i += 1;
+            if !stmt_start
+                || token.kind != Kind::Ident
+                || KEYWORDS.contains(&token.text)
+                || this.tokens.get(i).is_none_or(|eq| eq.text != "=")
+            {
+                continue;
+            }
+            let name = token.clone();
+            let end = this.tokens[i..].iter()
+                .position(|token| token.text == ";")
+                .map_or(this.tokens.len(), |len| i + len);
+            let kinds = this.infer(&this.tokens[i+1..end]);
+            match this.vars.iter_mut().find(|(var, _)| var.text == name.text) {
+                Some((_, all)) => *all = all.union(kinds),
+                None => this.vars.push((name, kinds)),
+            }
+        }
+        this
+    }
+
+    fn var(&self, name: &str) -> Option<&(Token<'a>, ValueKinds)> {
+        self.vars.iter().find(|(var, _)| var.text == name)
+    }
+
+    /// Infer by the first operand, binary operations keep the left hand side kind,
+    /// except comparisons and logic operations
+    fn infer(&self, expr: &[Token<'_>]) -> ValueKinds {
+        let mut depth = 0;
+        let mut result = None;
+        for token in expr {
+            match token.text {
+                "(" => depth += 1,
+                ")" => depth -= 1,
+                "&&" | "||" if depth == 0 => return ValueKinds::UNKNOWN,
+                "<" | "<=" | ">" | ">=" | "==" | "!=" if depth == 0 => result = Some(ValueKinds::BOOL),
+                _ => (),
+            }
+        }
+        if let Some(result) = result {
+            return result;
+        }
+        let Some(first) = expr.first() else { return ValueKinds::UNKNOWN };
+        match (first.kind, first.text) {
+            (_, "-") => ValueKinds::NUMBER,
+            (_, "!") => ValueKinds::BOOL,
+            (_, "(") => self.infer(&expr[1..]),
+            (Kind::Number, _) => ValueKinds::NUMBER,
+            (Kind::StringLit, _) => ValueKinds::STRING,
+            (_, "null") if expr.len() == 1 => ValueKinds::NULL,
+            (_, "inf" | "nan") if expr.len() == 1 => ValueKinds::NUMBER,
+            (Kind::Ident, name) if expr.get(1).is_none_or(|next| next.text != "(") => {
+                self.var(name).map_or(ValueKinds::UNKNOWN, |(_, kinds)| *kinds)
+            },
+            _ => ValueKinds::UNKNOWN,
+        }
+    }
+
+    fn ident_at(&self, at: usize) -> Option<&Token<'a>> {
+        self.tokens.iter().find(|token| {
+            token.kind == Kind::Ident
+                && !KEYWORDS.contains(&token.text)
+                && (token.span.start..=token.span.end).contains(&at)
+        })
+    }
+
+    fn hover(&self, src: &str, at: usize) -> Json {
+        let Some(token) = self.ident_at(at) else { return Json::Null };
+        let Some((_, kinds)) = self.var(token.text) else { return Json::Null };
+        Json::object([
+            ("contents", Json::object([
+                ("kind", "markdown".into()),
+                ("value", format!("```rsd\n{}: {kinds}\n```", token.text).into()),
+            ])),
+            ("range", range(src, token.span.clone())),
+        ])
+    }
+
+    fn definition(&self, src: &str, at: usize, uri: &str) -> Json {
+        let Some(token) = self.ident_at(at) else { return Json::Null };
+        let Some((def, _)) = self.var(token.text) else { return Json::Null };
+        Json::object([
+            ("uri", uri.into()),
+            ("range", range(src, def.span.clone())),
+        ])
+    }
+
+    fn symbols(&self, src: &str) -> Json {
+        self.vars.iter().map(|(def, kinds)| {
+            Json::object([
+                ("name", def.text.into()),
+                ("detail", kinds.to_string().into()),
+                ("kind", 13.into()),
+                ("range", range(src, def.span.clone())),
+                ("selectionRange", range(src, def.span.clone())),
+            ])
+        }).collect::<Vec<_>>().into()
+    }
+}
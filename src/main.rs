@@ -1,22 +1,156 @@
-use std::{env::args, process::exit};
+use std::{
+    env::args,
+    io::{Write, read_to_string, stdin, stdout},
+    path::{Path, PathBuf},
+    process::exit,
+};
 use fs_err as fs;
 
 use getopts_macro::getopts_options;
-use run_str_demo::{Config, Rt};
+use run_str_demo::{
+    Code, Config, Diagnostic, LineMode, NumberFormat, Rt, Severity, Status, Value,
+    format::format,
+    highlight::{self, highlight},
+    lsp,
+};
 
-struct Cfg;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ErrorFormat {
+    Human,
+    Json,
+}
+
+struct Cfg {
+    file: String,
+    error_format: ErrorFormat,
+    deny_warnings: bool,
+    strict: bool,
+    /// Canonical directories which file builtins may access
+    allow_dirs: Vec<PathBuf>,
+    seed: Option<u64>,
+    number_format: NumberFormat,
+}
 impl Config for Cfg {
     fn print(&mut self, args: std::fmt::Arguments<'_>) {
         print!("{args}");
     }
+
+    fn eprint(&mut self, args: std::fmt::Arguments<'_>) {
+        eprint!("{args}");
+    }
+
+    fn error(&self, diag: Diagnostic) -> ! {
+        self.report(&diag);
+        exit(1)
+    }
+
+    fn warn(&mut self, diag: Diagnostic) {
+        if self.deny_warnings {
+            self.error(Diagnostic { severity: Severity::Error, ..diag })
+        }
+        self.report(&diag);
+    }
+
+    fn strict(&self) -> bool {
+        self.strict
+    }
+
+    fn env(&self, name: &str) -> Option<String> {
+        std::env::var(name).ok()
+    }
+
+    fn read_file(&mut self, path: &str) -> Result<String, String> {
+        self.sandbox(path)?;
+        fs::read_to_string(path).map_err(|e| e.to_string())
+    }
+
+    fn write_file(&mut self, path: &str, contents: &str) -> Result<(), String> {
+        self.sandbox(path)?;
+        fs::write(path, contents).map_err(|e| e.to_string())
+    }
+
+    fn append_file(&mut self, path: &str, contents: &str) -> Result<(), String> {
+        self.sandbox(path)?;
+        fs::OpenOptions::new().create(true).append(true).open(path)
+            .and_then(|mut file| file.write_all(contents.as_bytes()))
+            .map_err(|e| e.to_string())
+    }
+
+    fn exists(&mut self, path: &str) -> Result<bool, String> {
+        self.sandbox(path)?;
+        fs::exists(path).map_err(|e| e.to_string())
+    }
+
+    fn number_format(&self) -> NumberFormat {
+        self.number_format.clone()
+    }
+
+    fn seed(&mut self) -> u64 {
+        self.seed.unwrap_or_else(|| self.now().to_bits())
+    }
+
+    fn read_line(&mut self) -> Option<String> {
+        let mut line = String::new();
+        match stdin().read_line(&mut line) {
+            Ok(0) => None,
+            Ok(_) => Some(line),
+            Err(e) => {
+                eprintln!("{e}");
+                exit(1)
+            },
+        }
+    }
+}
+impl Cfg {
+    /// Deny paths outside of `--allow-dir`,
+    /// symlinks and `..` are resolved up to the first missing component
+    fn sandbox(&self, path: &str) -> Result<(), String> {
+        if self.allow_dirs.is_empty() {
+            return Err("File access is not allowed, see `--allow-dir`".to_owned());
+        }
+        let resolved = resolve(Path::new(path)).map_err(|e| format!("{e}: {path:?}"))?;
+        if !self.allow_dirs.iter().any(|dir| resolved.starts_with(dir)) {
+            return Err(format!("Access denied, {path:?} is outside of `--allow-dir`"));
+        }
+        Ok(())
+    }
+
+    fn report(&self, diag: &Diagnostic) {
+        match self.error_format {
+            ErrorFormat::Human => eprintln!("{}: {diag}", self.file),
+            ErrorFormat::Json => eprintln!("{}", diag.to_json(&self.file)),
+        }
+    }
 }
 
 fn main() {
     let options = getopts_options! {
         -h, --help          "show help messages";
         -v, --version       "show version messages";
+        -e, --eval=CODE     "run CODE instead of reading <prog>";
+        -n, --lines         "run the program for each stdin line, \
+                             bound to `line`, and its number to `nr`";
+        -p, --print-lines   "like `-n`, and print `line` after each run";
+            --error-format=FORMAT "diagnostic format, `human` (default) or `json`";
+            --explain=CODE  "show the explanation of an error code";
+            --check         "only check the program without executing, \
+                             or with `fmt`, only check formatting";
+            --strict        "reject implicit coercions between types";
+        -D*=DEFINE          "`NAME=VALUE` defines a variable, a number if VALUE looks like one, \
+                             otherwise a string, `1` without VALUE; \
+                             `-D warnings` makes all warnings errors";
+            --format=FORMAT "highlight format, `ansi` (default) or `html`";
+            --allow-dir*=DIR "allow file builtins to access files inside DIR";
+            --seed=N        "seed of `random()` for reproducible runs, \
+                             time based by default";
+            --number-format=SPEC "format of printed numbers, like `fmt(n, SPEC)`";
+    };
+    let mut args: Vec<String> = args().skip(1).collect();
+    let script_args = match args.iter().position(|arg| arg == "--") {
+        Some(i) => args.split_off(i).split_off(1),
+        None => vec![],
     };
-    let matched = match options.parse(args().skip(1)) {
+    let matched = match options.parse(&args) {
         Ok(x) => x,
         Err(e) => {
             eprintln!("{e}");
@@ -25,7 +159,13 @@ fn main() {
     };
     if matched.opt_present("help") {
         let usage = options.short_usage(env!("CARGO_BIN_NAME"));
-        let brief = format!("{usage} <prog>");
+        let bin = env!("CARGO_BIN_NAME");
+        let brief = format!("{usage} <prog> [args..] [-- args..]\n       \
+                             {bin} fmt [--check] <prog>..\n       \
+                             {bin} highlight [--format FORMAT] <prog>\n       \
+                             {bin} lsp\n\n\
+                             <prog> is `-` to read from stdin, or omitted with `-e`,\n\
+                             script arguments after `--` are never parsed as options");
         let help = options.usage(&brief);
         print!("{help}");
         return;
@@ -34,20 +174,224 @@ fn main() {
         println!("{}", env!("CARGO_PKG_VERSION"));
         return;
     }
-    if let Some(first) = matched.free.get(1) {
-        eprintln!("Extra argument: {first:?}");
+    if let Some(code) = matched.opt_str("explain") {
+        let Some(code) = Code::parse(&code) else {
+            eprintln!("Unknown error code: {code:?}");
+            exit(2)
+        };
+        print!("{}", code.explain());
+        return;
+    }
+    let error_format = match matched.opt_str("error-format").as_deref() {
+        None | Some("human") => ErrorFormat::Human,
+        Some("json") => ErrorFormat::Json,
+        Some(format) => {
+            eprintln!("Invalid error format: {format:?}");
+            exit(2)
+        },
+    };
+    let mut deny_warnings = false;
+    let mut defines = vec![];
+    for define in matched.opt_strs("D") {
+        if define == "warnings" {
+            deny_warnings = true;
+            continue;
+        }
+        let (name, value) = match define.split_once('=') {
+            Some((name, value)) => (name, Value::parse_define(value)),
+            None => (&*define, Value::Number(1.0)),
+        };
+        if !is_ident(name) {
+            eprintln!("Invalid variable name: {name:?}");
+            exit(2)
+        }
+        defines.push((name.to_owned(), value));
+    }
+    let strict = matched.opt_present("strict");
+    let allow_dirs: Vec<PathBuf> = matched.opt_strs("allow-dir").iter().map(|dir| {
+        fs::canonicalize(dir).unwrap_or_else(|e| {
+            eprintln!("{e}");
+            exit(2)
+        })
+    }).collect();
+    let seed = matched.opt_str("seed").map(|seed| {
+        seed.parse().unwrap_or_else(|e| {
+            eprintln!("Invalid seed {seed:?}: {e}");
+            exit(2)
+        })
+    });
+    let number_format = matched.opt_str("number-format").map_or_else(Default::default, |spec| {
+        NumberFormat::parse(&spec).unwrap_or_else(|e| {
+            eprintln!("{e}");
+            exit(2)
+        })
+    });
+    let cfg = |file: &str| Cfg {
+        file: file.to_owned(),
+        error_format,
+        deny_warnings,
+        strict,
+        allow_dirs: allow_dirs.clone(),
+        seed,
+        number_format: number_format.clone(),
+    };
+    let eval = matched.opt_str("eval");
+    let subcommand = matched.free.first().map(String::as_str);
+    if let (Some("fmt" | "highlight" | "lsp"), Some(arg)) = (subcommand, script_args.first()) {
+        eprintln!("Extra argument: {arg:?}");
         exit(2)
     }
-    let prog = matched.free.first().unwrap_or_else(|| {
-        eprintln!("Expected <prog> position argument");
+    if subcommand == Some("fmt") {
+        return fmt(eval, &matched.free[1..], matched.opt_present("check"), cfg);
+    }
+    if subcommand == Some("lsp") {
+        if eval.is_some() {
+            eprintln!("Unexpected `-e` for lsp");
+            exit(2)
+        }
+        if let Err(e) = lsp::serve(stdin().lock(), stdout().lock()) {
+            eprintln!("{e}");
+            exit(1)
+        }
+        return;
+    }
+    if subcommand == Some("highlight") {
+        let format = match matched.opt_str("format").as_deref() {
+            None | Some("ansi") => highlight::Format::Ansi,
+            Some("html") => highlight::Format::Html,
+            Some(format) => {
+                eprintln!("Invalid highlight format: {format:?}");
+                exit(2)
+            },
+        };
+        let (_, prog, extra) = source(eval, &matched.free[1..]);
+        if let Some(extra) = extra.first() {
+            eprintln!("Extra argument: {extra:?}");
+            exit(2)
+        }
+        print!("{}", highlight(&prog, format));
+        return;
+    }
+    let line_mode = if matched.opt_present("print-lines") {
+        Some(LineMode::Print)
+    } else if matched.opt_present("lines") {
+        Some(LineMode::Each)
+    } else {
+        None
+    };
+    if line_mode.is_some() && matched.free.first().is_some_and(|path| path == "-") {
+        eprintln!("Cannot read <prog> from stdin with `-n` or `-p`");
         exit(2)
-    });
-    let prog = fs::read_to_string(prog).unwrap_or_else(|e| {
+    }
+    let (name, prog, args) = source(eval, &matched.free);
+
+    let mut rt = Rt::with_config(cfg(&name));
+    rt.load_source(&prog);
+    rt.set_args(args.iter().chain(&script_args).cloned());
+    for (name, value) in defines {
+        rt.set_var(name, value);
+    }
+    if let Some(mode) = line_mode {
+        rt.set_line_mode(mode);
+    }
+    rt.check();
+    if matched.opt_present("check") {
+        return;
+    }
+    if let Status::Exit(code) = rt.proc() {
+        exit(code)
+    }
+}
+
+/// Program name of `-e` in diagnostics
+const EVAL_NAME: &str = "<eval>";
+
+/// Name and source of the program from `-e` or a path, and the rest arguments
+fn source(eval: Option<String>, free: &[String]) -> (String, String, &[String]) {
+    match (eval, free) {
+        (Some(code), []) => (EVAL_NAME.to_owned(), code, &[]),
+        (Some(_), [path, ..]) => {
+            eprintln!("Unexpected <prog> argument with `-e`: {path:?}");
+            exit(2)
+        },
+        (None, [path, rest @ ..]) => (path.clone(), read(path), rest),
+        (None, []) => {
+            eprintln!("Expected <prog> position argument");
+            exit(2)
+        },
+    }
+}
+
+/// Canonicalize the existing ancestor of an absolute path, and append the rest components
+fn resolve(path: &Path) -> std::io::Result<PathBuf> {
+    let path = std::path::absolute(path)?;
+    let mut existing = path.as_path();
+    let mut rest = vec![];
+    loop {
+        match existing.canonicalize() {
+            Ok(dir) => return Ok(dir.join(rest.iter().rev().collect::<PathBuf>())),
+            Err(e) => {
+                let (Some(parent), Some(name)) = (existing.parent(), existing.file_name()) else {
+                    return Err(e);
+                };
+                rest.push(name);
+                existing = parent;
+            },
+        }
+    }
+}
+
+fn is_ident(name: &str) -> bool {
+    name.starts_with(|ch: char| ch.is_ascii_alphabetic() || ch == '_')
+        && name.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+}
+
+/// Read a file, `-` reads from stdin
+fn read(path: &str) -> String {
+    let src = if path == "-" {
+        read_to_string(stdin())
+    } else {
+        fs::read_to_string(path)
+    };
+    src.unwrap_or_else(|e| {
         eprintln!("{e}");
         exit(1)
-    });
+    })
+}
 
-    let mut rt = Rt::with_config(Cfg);
-    rt.load_source(&prog);
-    rt.proc();
+/// Format files in place, `-` and `-e` are written to stdout
+fn fmt(eval: Option<String>, paths: &[String], check: bool, cfg: impl Fn(&str) -> Cfg) {
+    let sources: Vec<(String, String)> = match eval {
+        Some(_) => {
+            let (name, src, _) = source(eval, paths);
+            vec![(name, src)]
+        },
+        None if paths.is_empty() => {
+            eprintln!("Expected <prog> position argument");
+            exit(2)
+        },
+        None => paths.iter().map(|path| (path.clone(), read(path))).collect(),
+    };
+    let mut unformatted = false;
+    for (path, src) in &sources {
+        let formatted = format(src).unwrap_or_else(|e| cfg(path).error(e));
+        let stdout = path == "-" || path == EVAL_NAME;
+        if formatted == *src && (check || !stdout) {
+            continue;
+        }
+        if check {
+            eprintln!("Would reformat: {path}");
+            unformatted = true;
+        } else if stdout {
+            print!("{formatted}");
+        } else {
+            fs::write(path, formatted).unwrap_or_else(|e| {
+                eprintln!("{e}");
+                exit(1)
+            });
+        }
+    }
+    if unformatted {
+        exit(1)
+    }
 }
@@ -0,0 +1,207 @@
+use std::iter;
+
+use crate::Value;
+
+/// Format spec of `fmt(value, spec)` and [`Config::number_format`],
+/// a subset of Python's format mini-language:
+///
+/// ```text
+/// [[fill]align][sign][0][width][grouping][.precision][type]
+/// ```
+///
+/// - align: `<` left, `>` right, `^` center, `=` pad after the sign
+/// - sign: `+` always, `-` only negative (default), space for a space on positive
+/// - `0`: pad numbers with zeros after the sign
+/// - grouping: `,` or `_` between thousands
+/// - type: `f` fixed, `e` `E` exponent, `g` general, `%` percent,
+///   none is the shortest representation, or general with a precision
+///
+/// NaN and infinities are written as `NaN` and `inf`, and never padded with zeros.
+/// The empty spec is the plain `print` output.
+///
+/// [`Config::number_format`]: crate::Config::number_format
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NumberFormat {
+    fill: Option<char>,
+    align: Option<char>,
+    sign: Option<char>,
+    zero: bool,
+    width: usize,
+    grouping: Option<char>,
+    precision: Option<usize>,
+    ty: Option<char>,
+}
+
+impl NumberFormat {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut format = Self::default();
+        let mut rest = spec;
+        let mut chars = rest.chars();
+        match (chars.next(), chars.next()) {
+            (Some(fill), Some(align @ ('<' | '>' | '^' | '='))) => {
+                format.fill = Some(fill);
+                format.align = Some(align);
+                rest = chars.as_str();
+            },
+            (Some(align @ ('<' | '>' | '^' | '=')), _) => {
+                format.align = Some(align);
+                rest = &rest[1..];
+            },
+            _ => (),
+        }
+        if let Some(sign @ ('+' | '-' | ' ')) = rest.chars().next() {
+            format.sign = Some(sign);
+            rest = &rest[1..];
+        }
+        if let Some(after) = rest.strip_prefix('0') {
+            format.zero = true;
+            rest = after;
+        }
+        let (width, after) = digits(rest, "width")?;
+        format.width = width.unwrap_or(0);
+        rest = after;
+        if let Some(grouping @ (',' | '_')) = rest.chars().next() {
+            format.grouping = Some(grouping);
+            rest = &rest[1..];
+        }
+        if let Some(after) = rest.strip_prefix('.') {
+            let (precision, after) = digits(after, "precision")?;
+            format.precision = Some(precision.ok_or("Expected precision after `.`")?);
+            rest = after;
+        }
+        if let Some(ty @ ('f' | 'e' | 'E' | 'g' | '%' | 's')) = rest.chars().next() {
+            format.ty = Some(ty);
+            rest = &rest[1..];
+        }
+        match rest.chars().next() {
+            Some(ch) => Err(format!("Unexpected `{ch}` in format spec {spec:?}")),
+            None => Ok(format),
+        }
+    }
+
+    /// Format numbers with the spec, other values are not changed
+    pub fn apply(&self, value: &Value) -> String {
+        match value {
+            Value::Number(n) if *self != Self::default() => self.number(*n),
+            _ => value.to_string(),
+        }
+    }
+
+    /// Format any value, strings only support fill, align, width and precision
+    pub(crate) fn value(&self, value: &Value) -> Result<String, String> {
+        let s = match value {
+            Value::Number(n) if self.ty != Some('s') => return Ok(self.number(*n)),
+            Value::Number(_) | Value::String(_) | Value::Null => value.to_string(),
+        };
+        if self.sign.is_some() || self.zero || self.grouping.is_some() || self.align == Some('=')
+            || self.ty.is_some_and(|ty| ty != 's')
+        {
+            return Err(format!("Number format spec used for {}", value.type_name()));
+        }
+        let s = match self.precision {
+            Some(precision) => s.chars().take(precision).collect(),
+            None => s,
+        };
+        Ok(self.pad("", &s, '<'))
+    }
+
+    fn number(&self, n: f64) -> String {
+        let sign = if n.is_sign_negative() && !n.is_nan() {
+            "-"
+        } else {
+            match self.sign {
+                Some('+') => "+",
+                Some(' ') => " ",
+                _ => "",
+            }
+        };
+        let n = n.abs();
+        if !n.is_finite() {
+            let body = if n.is_nan() { "NaN" } else { "inf" };
+            let format = Self { zero: false, ..self.clone() };
+            return format.pad(sign, body, '>');
+        }
+        let mut body = match (self.ty, self.precision) {
+            (Some('f'), precision) => format!("{n:.*}", precision.unwrap_or(6)),
+            (Some('%'), precision) => format!("{:.*}%", precision.unwrap_or(6), n * 100.0),
+            (Some(ty @ ('e' | 'E')), precision) => exponent(n, precision.unwrap_or(6), ty),
+            (Some('g'), precision) | (_, precision @ Some(_)) => general(n, precision.unwrap_or(6)),
+            (_, None) => n.to_string(),
+        };
+        if let Some(sep) = self.grouping {
+            let int_len = body.find(|ch: char| !ch.is_ascii_digit()).unwrap_or(body.len());
+            let (int, frac) = body.split_at(int_len);
+            body = group(int, sep) + frac;
+        }
+        self.pad(sign, &body, '>')
+    }
+
+    fn pad(&self, sign: &str, body: &str, default_align: char) -> String {
+        let len = sign.chars().count() + body.chars().count();
+        let padding = self.width.saturating_sub(len);
+        let (fill, align) = match (self.fill, self.align) {
+            (None, None) if self.zero => ('0', '='),
+            (fill, align) => (fill.unwrap_or(' '), align.unwrap_or(default_align)),
+        };
+        let fill = |n| iter::repeat_n(fill, n).collect::<String>();
+        match align {
+            '<' => format!("{sign}{body}{}", fill(padding)),
+            '^' => format!("{}{sign}{body}{}", fill(padding / 2), fill(padding - padding / 2)),
+            '=' => format!("{sign}{}{body}", fill(padding)),
+            _ => format!("{}{sign}{body}", fill(padding)),
+        }
+    }
+}
+
+/// Largest width and precision, formatting panics far above
+const MAX_DIGITS: usize = 10_000;
+
+fn digits<'a>(s: &'a str, name: &str) -> Result<(Option<usize>, &'a str), String> {
+    let len = s.find(|ch: char| !ch.is_ascii_digit()).unwrap_or(s.len());
+    if len == 0 {
+        return Ok((None, s));
+    }
+    match s[..len].parse() {
+        Ok(n) if n <= MAX_DIGITS => Ok((Some(n), &s[len..])),
+        _ => Err(format!("Format spec {name} {} too large, at most {MAX_DIGITS}", &s[..len])),
+    }
+}
+
+/// Python style exponent, with a sign and at least two digits
+fn exponent(n: f64, precision: usize, ty: char) -> String {
+    let s = format!("{n:.precision$e}");
+    let (mantissa, exp) = s.split_once('e').unwrap();
+    let exp: i32 = exp.parse().unwrap();
+    let sign = if exp < 0 { '-' } else { '+' };
+    format!("{mantissa}{ty}{sign}{:02}", exp.abs())
+}
+
+/// Fixed or exponent by the magnitude, without trailing zeros
+fn general(n: f64, precision: usize) -> String {
+    let precision = precision.max(1);
+    let rounded = format!("{n:.*e}", precision - 1);
+    let exp: i32 = rounded.split_once('e').unwrap().1.parse().unwrap();
+    let mut s = if (-4..precision as i32).contains(&exp) {
+        format!("{n:.*}", (precision as i32 - 1 - exp) as usize)
+    } else {
+        exponent(n, precision - 1, 'e')
+    };
+    let exp_at = s.find('e').unwrap_or(s.len());
+    let mut mantissa = s[..exp_at].to_owned();
+    if mantissa.contains('.') {
+        mantissa.truncate(mantissa.trim_end_matches('0').trim_end_matches('.').len());
+    }
+    s.replace_range(..exp_at, &mantissa);
+    s
+}
+
+fn group(int: &str, sep: char) -> String {
+    let mut buf = String::with_capacity(int.len() + int.len() / 3);
+    for (i, ch) in int.chars().enumerate() {
+        if i != 0 && (int.len() - i).is_multiple_of(3) {
+            buf.push(sep);
+        }
+        buf.push(ch);
+    }
+    buf
+}
@@ -0,0 +1,35 @@
+/// SplitMix64, only integer arithmetic, so the same seed
+/// produces the same sequence on every platform
+#[derive(Debug, Clone)]
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`
+    pub(crate) fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform in `0..=max`, without modulo bias
+    pub(crate) fn below_or_eq(&mut self, max: u64) -> u64 {
+        let Some(span) = max.checked_add(1) else { return self.next_u64() };
+        let zone = u64::MAX - u64::MAX % span;
+        loop {
+            let n = self.next_u64();
+            if n < zone {
+                return n % span;
+            }
+        }
+    }
+}
@@ -1,19 +1,54 @@
 use expect_test::{Expect, expect};
+use std::collections::VecDeque;
 
 use super::*;
 
 #[derive(Debug)]
-struct Output(String);
+struct Output {
+    out: String,
+    /// Output of [`Config::eprint`]
+    err: String,
+    /// Lines for [`Config::read_line`]
+    input: VecDeque<String>,
+    /// Virtual clock, only advanced by [`Config::sleep`]
+    clock: f64,
+}
+impl Default for Output {
+    fn default() -> Self {
+        Self {
+            out: String::new(),
+            err: String::new(),
+            input: VecDeque::new(),
+            clock: 1_700_000_000.0,
+        }
+    }
+}
 impl Config for Output {
     fn print(&mut self, args: fmt::Arguments<'_>) {
-        fmt::write(&mut self.0, args).unwrap();
+        fmt::write(&mut self.out, args).unwrap();
+    }
+
+    fn eprint(&mut self, args: fmt::Arguments<'_>) {
+        fmt::write(&mut self.err, args).unwrap();
+    }
+
+    fn read_line(&mut self) -> Option<String> {
+        self.input.pop_front()
+    }
+
+    fn now(&mut self) -> f64 {
+        self.clock
+    }
+
+    fn sleep(&mut self, seconds: f64) {
+        self.clock += seconds;
     }
 }
 
 #[track_caller]
 fn check(src: &str, expect: Expect) {
     let rt = run(src);
-    let actual = rt.cfg.0.as_str();
+    let actual = rt.cfg.out.as_str();
     if actual == "\n" {
         expect.assert_eq("<only has empty newline>");
     } else {
@@ -28,7 +63,7 @@ fn run(src: &str) -> Rt<'_, Output> {
     } else {
         println!("Run case `{}`", src.trim());
     }
-    let mut rt = Rt::with_config(Output(String::new()));
+    let mut rt = Rt::with_config(Output::default());
     rt.load_source(src);
     rt.proc();
 
@@ -66,6 +101,76 @@ fn comments() {
     "#]]);
 }
 
+#[test]
+fn write() {
+    check("write 1; write 'a'; print 2;", expect![[r#"
+        1a2
+    "#]]);
+    check("write 'a'; write null;", expect!["aNULL"]);
+    check(r#"write "a\n"; if null { write 'b'; }"#, expect![[r#"
+        a
+    "#]]);
+}
+
+/// Check both `print` and `eprint` output
+#[track_caller]
+fn check_streams(src: &str, expect: Expect) {
+    let rt = run(src);
+    expect.assert_eq(&format!("stdout:\n{}stderr:\n{}", rt.cfg.out, rt.cfg.err));
+}
+
+#[test]
+fn eprint() {
+    check_streams("print 1; eprint 'warn'; print 2;", expect![[r#"
+        stdout:
+        1
+        2
+        stderr:
+        warn
+    "#]]);
+    check_streams("eprint null; eprint 1 + 2;", expect![[r#"
+        stdout:
+        stderr:
+        NULL
+        3
+    "#]]);
+    check_streams("if null { eprint 'a'; } print 'b';", expect![[r#"
+        stdout:
+        b
+        stderr:
+    "#]]);
+}
+
+#[test]
+fn emit_typed_values() {
+    #[derive(Default)]
+    struct Events(Vec<Value>, String);
+    impl Config for Events {
+        fn print(&mut self, args: fmt::Arguments<'_>) {
+            fmt::write(&mut self.1, args).unwrap();
+        }
+
+        fn emit(&mut self, value: &Value) {
+            self.0.push(value.clone());
+        }
+    }
+    let mut rt = Rt::with_config(Events::default());
+    rt.load_source("print 1; print 'a'; print null; write 'text';");
+    rt.proc();
+    expect![[r#"
+        [
+            Number(
+                1.0,
+            ),
+            String(
+                "a",
+            ),
+            Null,
+        ]
+    "#]].assert_debug_eq(&rt.cfg.0);
+    assert_eq!(rt.cfg.1, "text");
+}
+
 #[test]
 fn multi_print_number() {
     check("print 2; print 3;", expect![[r#"
@@ -459,6 +564,166 @@ fn print_soft_string() {
     "#]]);
 }
 
+#[test]
+fn soft_string_escapes() {
+    let rt = run(r#"print "\u{1F600}\u{e9}\u{0}\x41\x7f\0\'\"\\\$\r";"#);
+    expect![[r#"
+        "😀é\0A\u{7f}\0'\"\\$\r\n"
+    "#]].assert_debug_eq(&rt.cfg.out);
+    check("print \"a\\\n    b \\\r\n\tc\\\n\n  d\";", expect![[r#"
+        ab c
+          d
+    "#]]);
+}
+
+#[test]
+fn soft_string_invalid_escapes() {
+    let escapes = [
+        r"\q", r"\u{110000}", r"\u{D800}", r"\u{dfff}", r"\u{}", r"\u{1234567}", r"\u{12",
+        r"\u{1g}", r"\u41", r"\x8", r"\x80", r"\xZ0", "\\é",
+    ];
+    let mut actual = String::new();
+    for escape in escapes {
+        let src = format!("print 'x' + \"ab{escape}\";");
+        let err = std::panic::catch_unwind(|| run(&src)).unwrap_err();
+        actual += err.downcast_ref::<String>().unwrap();
+        actual += "\n";
+    }
+    expect![[r#"
+        error[E0003]: Invalid soft string escape `\q` at 1:16 `\q";`
+        error[E0003]: Unicode escape `\u{110000}` out of range, must be at most `\u{10FFFF}` at 1:16 `\u{110000}";`
+        error[E0003]: Unicode escape `\u{D800}` is a surrogate at 1:16 `\u{D800}";`
+        error[E0003]: Unicode escape `\u{dfff}` is a surrogate at 1:16 `\u{dfff}";`
+        error[E0003]: Empty unicode escape at 1:16 `\u{}";`
+        error[E0003]: Unicode escape `\u{1234567}` has more than 6 hex digits at 1:16 `\u{1234567}";`
+        error[E0003]: Unicode escape not terminated by `}` at 1:16 `\u{12";`
+        error[E0003]: Invalid character `g` in unicode escape at 1:16 `\u{1g}";`
+        error[E0003]: Unicode escape must be braced, like `\u{1F600}` at 1:16 `\u41";`
+        error[E0003]: Hex escape must have two hex digits, like `\x7F` at 1:16 `\x8";`
+        error[E0003]: Hex escape `\x80` out of range, must be at most `\x7F` at 1:16 `\x80";`
+        error[E0003]: Hex escape must have two hex digits, like `\x7F` at 1:16 `\xZ0";`
+        error[E0003]: Invalid soft string escape `\é` at 1:16 `\é";`
+    "#]].assert_eq(&actual);
+}
+
+#[test]
+fn interpolation() {
+    check(r#"
+        name = 'World'; count = 2;
+        print "Hello ${name}, you have ${count + 1} items";
+        print "${ 1 / 4 }|${null}|${"in ${name + '}'}"}|${(1)}${2}";
+        print "\${name} $name {}";
+        print 'hard ${name}';
+    "#, expect![[r#"
+        Hello World, you have 3 items
+        0.25||in World}|12
+        ${name} $name {}
+        hard ${name}
+    "#]]);
+}
+
+#[test]
+fn interpolation_skipped() {
+    check(r#"
+        n = 0;
+        if null { print "${input()} ${sleep(1)}"; }
+        print "${n}" || "${missing}";
+    "#, expect![[r#"
+        0
+    "#]]);
+}
+
+#[test]
+#[should_panic = "error[E0007]: Expected a `}` closing the interpolation at 1:12"]
+fn interpolation_unclosed_expression() {
+    run(r#"print "${1 2}";"#);
+}
+
+#[test]
+fn triple_quoted_strings() {
+    check(r#"
+        name = 'x';
+        print '''it's "raw" \n ${name}''';
+        print """a "quoted" ${name}""" + ''''' ''';
+        print """
+            SELECT *
+              FROM "${name}"
+
+            WHERE a = '\x41' \
+                AND b
+            """;
+        print '''
+          a
+            b
+          ''' + '|';
+        print '''first line
+            kept''';
+        print """
+        """ + '|';
+    "#, expect![[r#"
+        it's "raw" \n ${name}
+        a "quoted" x'' 
+        SELECT *
+          FROM "x"
+
+        WHERE a = 'A' AND b
+        a
+          b|
+        first line
+                    kept
+        |
+    "#]]);
+}
+
+#[test]
+fn heredoc_strings() {
+    check(r#"
+        name = 'x';
+        if 1 {
+            print <<EOF
+                Hello ${name},
+                  "quoted" ''' \u{41}
+                EOFX
+                EOF;
+            print <<'END'
+              raw ${name} \n
+            END + '|';
+        }
+        print <<EOF
+        EOF + '|';
+    "#, expect![[r#"
+        Hello x,
+          "quoted" ''' A
+        EOFX
+        raw ${name} \n|
+        |
+    "#]]);
+}
+
+#[test]
+fn heredoc_crlf() {
+    let rt = run("print <<EOF\r\n  a\r\n  b\r\n  EOF;\r\n");
+    expect![[r#"
+        "a\r\nb\n"
+    "#]].assert_debug_eq(&rt.cfg.out);
+}
+
+#[test]
+fn multi_line_strings_skipped() {
+    check(r#"
+        if null {
+            print """
+                ${missing}
+            """ + <<EOF
+                ${missing}
+                EOF;
+        }
+        print 'done';
+    "#, expect![[r#"
+        done
+    "#]]);
+}
+
 #[test]
 fn assign() {
     check("x = 2; print x;", expect![[r#"
@@ -473,6 +738,92 @@ fn assign() {
     "#]]);
 }
 
+#[test]
+fn set_and_get_var() {
+    let mut rt = Rt::with_config(Output::default());
+    rt.load_source("print name + 1; count = count + 1;");
+    rt.set_var("name", Value::String("x".to_owned()));
+    rt.set_var(String::from("count"), Value::Number(2.0));
+    rt.check();
+    rt.proc();
+    assert_eq!(rt.cfg.out, "x1\n");
+    assert_eq!(rt.get_var("count"), Some(&Value::Number(3.0)));
+    assert_eq!(rt.get_var("name"), Some(&Value::String("x".to_owned())));
+    assert_eq!(rt.get_var("missing"), None);
+}
+
+#[track_caller]
+fn check_exit(src: &str, expect: Expect) {
+    let mut rt = Rt::with_config(Output::default());
+    rt.load_source(src);
+    rt.check();
+    let status = rt.proc();
+    assert_eq!(rt.ignore_level, 0, "Not cleanly effects");
+    expect.assert_eq(&format!("{status:?}\n{}", rt.cfg.out));
+}
+
+#[test]
+fn exit() {
+    check_exit("print 1; exit 3; print 2;", expect![[r#"
+        Exit(3)
+        1
+    "#]]);
+    check_exit("print 1;", expect![[r#"
+        Done
+        1
+    "#]]);
+    check_exit("exit 255;", expect![[r#"
+        Exit(255)
+    "#]]);
+    check_exit("exit 2.9;", expect![[r#"
+        Exit(2)
+    "#]]);
+    check_exit("exit null;", expect![[r#"
+        Exit(0)
+    "#]]);
+    check_exit("if null { exit 1; } print 'after';", expect![[r#"
+        Done
+        after
+    "#]]);
+    check_exit(r#"
+        i = 0;
+        while i < 10 {
+            if i == 2 { exit i + 40; }
+            print i;
+            i = i + 1;
+        }
+        print 'unreachable';
+    "#, expect![[r#"
+        Exit(42)
+        0
+        1
+    "#]]);
+}
+
+#[test]
+fn exit_out_of_range() {
+    let mut actual = String::new();
+    for code in ["-1", "256", "1e10", "nan", "-inf"] {
+        let src = format!("exit {code} ;");
+        let err = std::panic::catch_unwind(|| run(&src)).unwrap_err();
+        actual += err.downcast_ref::<String>().unwrap();
+        actual += "\n";
+    }
+    expect![[r#"
+        error[E0016]: Exit code -1 out of range 0 to 255 at 1:6 `-1 ;`
+        error[E0016]: Exit code 256 out of range 0 to 255 at 1:6 `256 ;`
+        error[E0016]: Exit code 10000000000 out of range 0 to 255 at 1:6 `1e10 ;`
+        error[E0016]: Exit code NaN out of range 0 to 255 at 1:6 `nan ;`
+        error[E0016]: Exit code -inf out of range 0 to 255 at 1:6 `-inf ;`
+    "#]].assert_eq(&actual);
+}
+
+#[test]
+#[should_panic = "error[E0006]: Expected a semicolon"]
+fn exit_checks_rest() {
+    check_exit("exit 1; print 2", expect![""]);
+}
+
 #[test]
 #[should_panic = "Invalid input"]
 fn unknown_input() {
@@ -561,3 +912,1408 @@ fn while_loop() {
         i: 3
     "#]]);
 }
+
+#[track_caller]
+fn check_error_json(src: &str, expect: Expect) {
+    struct Catch;
+    impl Config for Catch {
+        fn print(&mut self, _args: fmt::Arguments<'_>) {}
+
+        fn error(&self, diag: Diagnostic) -> ! {
+            std::panic::resume_unwind(Box::new(diag))
+        }
+    }
+    let mut rt = Rt::with_config(Catch);
+    rt.load_source(src);
+    let payload = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        rt.proc()
+    })).expect_err("expected an error");
+    let diag = payload.downcast::<Diagnostic>().unwrap();
+    expect.assert_eq(&diag.to_json("test.rsd"));
+}
+
+#[test]
+fn error_json() {
+    check_error_json("print x;", expect![[r#"{"severity":"error","code":"E0004","message":"Unknown variable `x`","file":"test.rsd","byte_start":6,"byte_end":7,"line_start":1,"column_start":7,"line_end":1,"column_end":8,"preview":"x;"}"#]]);
+    check_error_json("print 1;\n  print 'a\nb", expect![[r#"{"severity":"error","code":"E0002","message":"String literal not terminated","file":"test.rsd","byte_start":17,"byte_end":19,"line_start":2,"column_start":9,"line_end":2,"column_end":11,"preview":"'a"}"#]]);
+    check_error_json("print \"a\\q\";", expect![[r#"{"severity":"error","code":"E0003","message":"Invalid soft string escape `\\q`","file":"test.rsd","byte_start":8,"byte_end":10,"line_start":1,"column_start":9,"line_end":1,"column_end":11,"preview":"\\q\";"}"#]]);
+    check_error_json("print 1", expect![[r#"{"severity":"error","code":"E0006","message":"Expected a semicolon","file":"test.rsd","byte_start":7,"byte_end":7,"line_start":1,"column_start":8,"line_end":1,"column_end":8,"preview":""}"#]]);
+}
+
+#[test]
+#[should_panic = "error[E0004]: Unknown variable `x`"]
+fn error_code_in_message() {
+    run("print x;");
+}
+
+#[test]
+fn error_codes_explained() {
+    for &code in Code::ALL {
+        assert_eq!(Code::parse(code.as_str()), Some(code));
+        assert!(code.explain().contains("```"), "{code} has no example");
+    }
+    assert_eq!(Code::parse("E9999"), None);
+}
+
+#[track_caller]
+fn check_warnings(src: &str, expect: Expect) {
+    #[derive(Default)]
+    struct Warnings(String);
+    impl Config for Warnings {
+        fn print(&mut self, _args: fmt::Arguments<'_>) {}
+
+        fn warn(&mut self, diag: Diagnostic) {
+            self.0 += &format!("{diag}\n");
+        }
+    }
+    let mut rt = Rt::with_config(Warnings::default());
+    rt.load_source(src);
+    rt.check();
+    rt.proc();
+    expect.assert_eq(&rt.cfg.0);
+}
+
+mod lints {
+    use super::*;
+
+    #[test]
+    fn unused_variable() {
+        check_warnings("x = 1; y = 2; print y;", expect![[r#"
+            warning[W0001]: Variable `x` is assigned but never read at 1:1 `x = 1; y = 2; print y;`
+        "#]]);
+        check_warnings("x = 1; x = 2;", expect![[r#"
+            warning[W0001]: Variable `x` is assigned but never read at 1:1 `x = 1; x = 2;`
+        "#]]);
+        check_warnings("_x = 1;", expect![""]);
+        check_warnings("x = 1; if null { print x; }", expect![""]);
+    }
+
+    #[test]
+    fn constant_condition() {
+        check_warnings("while null { print 1; }", expect![[r#"
+            warning[W0002]: Condition of `while` is a constant at 1:7 `null { print 1; }`
+        "#]]);
+        check_warnings("while 'a' && null { print 1; }", expect![""]);
+        check_warnings("x = null; while x { print 1; }", expect![""]);
+    }
+
+    #[test]
+    fn mixed_comparison() {
+        check_warnings("print 1 < 'a';", expect![[r#"
+            warning[W0003]: Comparison `<` between number and string is ordered by type at 1:9 `< 'a';`
+        "#]]);
+        check_warnings("print null >= 1;", expect![[r#"
+            warning[W0003]: Comparison `>=` between null and number is ordered by type at 1:12 `>= 1;`
+        "#]]);
+        check_warnings("print 1 == 'a'; print 1 < 2;", expect![""]);
+    }
+
+    #[test]
+    fn string_coercion() {
+        check_warnings("print 2 + 'a';", expect![[r#"
+            warning[W0004]: Operation `+` between number and string silently coerces at 1:9 `+ 'a';`
+        "#]]);
+        check_warnings("print 'a.1' - 1;", expect![[r#"
+            warning[W0004]: Operation `-` between string and number silently coerces at 1:13 `- 1;`
+        "#]]);
+        check_warnings("print 'ab' * 'x';", expect![[r#"
+            warning[W0004]: Operation `*` between string and string silently coerces at 1:12 `* 'x';`
+        "#]]);
+        check_warnings("print 'a' + 2; print 'ab' * 2; print 'a' - 'b';", expect![""]);
+    }
+
+    #[test]
+    fn warn_once_in_loop() {
+        check_warnings(r#"
+            i = 0;
+            while i < 3 {
+                print 2 + 'a';
+                i = i + 1;
+            }
+        "#, expect![[r#"
+            warning[W0004]: Operation `+` between number and string silently coerces at 4:25 `+ 'a';`
+        "#]]);
+    }
+}
+
+#[test]
+#[should_panic = "Invalid expression Punct"]
+fn check_skipped_expression() {
+    run("if null { print ); }");
+}
+
+mod strict {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Strict(String);
+    impl Config for Strict {
+        fn print(&mut self, args: fmt::Arguments<'_>) {
+            fmt::write(&mut self.0, args).unwrap();
+        }
+
+        fn strict(&self) -> bool {
+            true
+        }
+    }
+
+    #[track_caller]
+    fn check(src: &str, expect: Expect) {
+        let mut rt = Rt::with_config(Strict(String::new()));
+        rt.load_source(src);
+        rt.proc();
+        expect.assert_eq(&rt.cfg.0);
+    }
+
+    #[test]
+    fn allowed() {
+        check(r#"
+            print 1 + 2 * 3;
+            print 'a' + 'b';
+            print 'a.b' - '.';
+            print 'a' < 'b';
+            print 1 == 'a';
+            print null != 2;
+            print 1 && 'a';
+        "#, expect![[r#"
+            7
+            ab
+            ab
+            1
+            NULL
+            1
+            a
+        "#]]);
+    }
+
+    #[test]
+    #[should_panic = "error[E0011]: Operation `+` between string and number in strict mode at 1:11"]
+    fn string_add_number() {
+        check("print 'a' + 1;", expect![""]);
+    }
+
+    #[test]
+    #[should_panic = "Operation `*` between string and number in strict mode"]
+    fn string_mul() {
+        check("print 'abc' * -1;", expect![""]);
+    }
+
+    #[test]
+    #[should_panic = "Operation `+` between null and number in strict mode"]
+    fn null_add() {
+        check("print null + 1;", expect![""]);
+    }
+
+    #[test]
+    #[should_panic = "Operation `<` between number and string in strict mode"]
+    fn mixed_cmp() {
+        check("print 1 < 'a';", expect![""]);
+    }
+
+    #[test]
+    fn skipped() {
+        check("if null { print 'a' * 2; } print null || 1 < 2;", expect![[r#"
+            1
+        "#]]);
+    }
+}
+
+mod undefined {
+    use super::*;
+
+    #[test]
+    fn defined() {
+        check_warnings(r#"
+            x = 1;
+            if x { y = x; print y; }
+            while x { z = 1; print z; x = null; }
+            print x;
+        "#, expect![""]);
+    }
+
+    #[test]
+    fn maybe_in_if() {
+        check_warnings(r#"
+            if 1 { x = 1; }
+            print x;
+        "#, expect![[r#"
+            warning[W0005]: Variable `x` may be used before assignment at 3:19 `x;`
+        "#]]);
+    }
+
+    #[test]
+    fn maybe_in_while() {
+        check_warnings(r#"
+            i = 0;
+            while i < 2 {
+                if i > 0 { print x; }
+                x = i;
+                i = i + 1;
+            }
+        "#, expect![[r#"
+            warning[W0005]: Variable `x` may be used before assignment at 4:34 `x; }`
+        "#]]);
+    }
+
+    #[test]
+    fn self_assign() {
+        check_warnings(r#"
+            if 1 { x = 1; }
+            x = x + 1;
+            print x;
+        "#, expect![[r#"
+            warning[W0005]: Variable `x` may be used before assignment at 3:17 `x + 1;`
+        "#]]);
+    }
+
+    #[test]
+    fn unknown_in_skipped_branch() {
+        check_warnings(r#"
+            x = null;
+            if x { print y; }
+        "#, expect![[r#"
+            warning[W0006]: Variable `y` is never assigned at 3:26 `y; }`
+        "#]]);
+    }
+
+    #[test]
+    fn maybe_in_interpolation() {
+        check_warnings(r#"
+            if 1 { x = 1; }
+            print "a ${1 + x}";
+        "#, expect![[r#"
+            warning[W0005]: Variable `x` may be used before assignment at 3:28 `x}";`
+        "#]]);
+    }
+
+    #[test]
+    fn unknown_in_interpolation() {
+        check_warnings(r#"if null { print "a ${1 + y}"; }"#, expect![[r#"
+            warning[W0006]: Variable `y` is never assigned at 1:26 `y}"; }`
+        "#]]);
+    }
+
+    #[test]
+    fn unknown_short_circuit() {
+        check_warnings("print 1 || y; print null && y;", expect![[r#"
+            warning[W0006]: Variable `y` is never assigned at 1:12 `y; print null && y;`
+            warning[W0006]: Variable `y` is never assigned at 1:29 `y;`
+        "#]]);
+    }
+
+    #[test]
+    #[should_panic = "Unknown variable `x`"]
+    fn unknown_in_condition() {
+        check_warnings("while x { x = 1; }", expect![""]);
+    }
+}
+
+#[track_caller]
+fn check_format(src: &str, expect: Expect) {
+    let format = |src| format::format(src).unwrap();
+    let formatted = format(src);
+    expect.assert_eq(&formatted);
+    assert_eq!(format(&formatted), formatted, "Not idempotent");
+    assert_eq!(run(&formatted).cfg.out, run(src).cfg.out, "Output changed");
+}
+
+mod formatter {
+    use super::*;
+
+    #[test]
+    fn statements() {
+        check_format("print 1;print 2;  x=3 ;print x;", expect![[r#"
+            print 1;
+            print 2;
+            x = 3;
+            print x;
+        "#]]);
+    }
+
+    #[test]
+    fn operators() {
+        check_format("print 1+2*3-(4/2)%3;print -1;print - -1;print 3- -2;", expect![[r#"
+            print 1 + 2 * 3 - (4 / 2) % 3;
+            print -1;
+            print - -1;
+            print 3 - -2;
+        "#]]);
+        check_format("print !null&&1||2;print 1<2==(3>=4);print !(1-2);", expect![[r#"
+            print !null && 1 || 2;
+            print 1 < 2 == (3 >= 4);
+            print !(1 - 2);
+        "#]]);
+    }
+
+    #[test]
+    fn adjacent_operators() {
+        check_format("print 1--2;print 1 - - 2;", expect![[r#"
+            print 1 - -2;
+            print 1 - -2;
+        "#]]);
+        // invalid, but not split into other operators
+        let formatted = format::format("print 1; /* */").unwrap();
+        expect![[r#"
+            print 1;
+            /* */
+        "#]].assert_eq(&formatted);
+        assert_eq!(format::format(&formatted).unwrap(), formatted, "Not idempotent");
+    }
+
+    #[test]
+    fn blocks() {
+        check_format("if 1{print 1;if null{print 2;}}{print 3;}", expect![[r#"
+            if 1 {
+                print 1;
+                if null {
+                    print 2;
+                }
+            }
+            {
+                print 3;
+            }
+        "#]]);
+        check_format(r#"
+            i=0;
+            while i<3 {
+            print i;
+
+
+            i=i+1;
+            }
+            if 1 {}
+        "#, expect![[r#"
+            i = 0;
+            while i < 3 {
+                print i;
+
+                i = i + 1;
+            }
+            if 1 {}
+        "#]]);
+    }
+
+    #[test]
+    fn comments() {
+        check_format(r#"
+            // head
+
+            print 1; // trailing
+            // own line
+            if 1 { // open
+                print 2;
+                // before close
+            }
+            print 3 + // inside
+              4;
+            // tail
+        "#, expect![[r#"
+            // head
+
+            print 1; // trailing
+            // own line
+            if 1 { // open
+                print 2;
+                // before close
+            }
+            print 3 + // inside
+                4;
+            // tail
+        "#]]);
+    }
+
+    #[test]
+    fn empty() {
+        check_format("", expect![""]);
+        check_format("  // only\n", expect![[r#"
+            // only
+        "#]]);
+    }
+}
+
+#[track_caller]
+fn check_tokens(lexer: Lexer<'_>, expect: Expect) {
+    let mut actual = String::new();
+    for token in lexer {
+        match token {
+            Ok(Token { kind, text, span }) => {
+                actual += &format!("{kind:?} {span:?} {text:?}\n");
+            },
+            Err(e) => actual += &format!("{e}\n"),
+        }
+    }
+    expect.assert_eq(&actual);
+}
+
+mod lexer {
+    use super::*;
+
+    #[test]
+    fn tokens() {
+        check_tokens(Lexer::new("x = 'a' + 2.5; // c\nprint x<=\"b\\\"\";"), expect![[r#"
+            Ident 0..1 "x"
+            Punct 2..3 "="
+            StringLit 4..7 "'a'"
+            Punct 8..9 "+"
+            Number 10..13 "2.5"
+            Punct 13..14 ";"
+            Ident 20..25 "print"
+            Ident 26..27 "x"
+            Punct 27..29 "<="
+            StringLit 29..34 "\"b\\\"\""
+            Punct 34..35 ";"
+        "#]]);
+    }
+
+    #[test]
+    fn trivia() {
+        check_tokens(Lexer::new(" x // c\n\t;").with_trivia(true), expect![[r#"
+            Whitespace 0..1 " "
+            Ident 1..2 "x"
+            Whitespace 2..3 " "
+            Comment 3..7 "// c"
+            Whitespace 7..9 "\n\t"
+            Punct 9..10 ";"
+        "#]]);
+    }
+
+    #[test]
+    fn unknown() {
+        check_tokens(Lexer::new("print @测;"), expect![[r#"
+            Ident 0..5 "print"
+            Unknown 6..7 "@"
+            Unknown 7..10 "测"
+            Punct 10..11 ";"
+        "#]]);
+    }
+
+    #[test]
+    fn unterminated() {
+        check_tokens(Lexer::new("print 'a;\nprint 2;"), expect![[r#"
+            Ident 0..5 "print"
+            error[E0002]: String literal not terminated at 1:7 `'a;`
+        "#]]);
+    }
+
+    #[test]
+    fn interpolation() {
+        check_tokens(Lexer::new(r#"print "a ${"}" + {'"'}} \${" $ "${ {}";"#), expect![[r#"
+            Ident 0..5 "print"
+            StringLit 6..28 "\"a ${\"}\" + {'\"'}} \\${\""
+            Unknown 29..30 "$"
+            error[E0002]: String interpolation not terminated at 1:33 `${ {}";`
+        "#]]);
+        check_tokens(Lexer::new("print \"${'a}\";\nprint 2;"), expect![[r#"
+            Ident 0..5 "print"
+            error[E0002]: String interpolation not terminated at 1:8 `${'a}";`
+        "#]]);
+    }
+
+    #[test]
+    fn multi_line_strings() {
+        check_tokens(Lexer::new("'''a''' '''b'c''' \"\"\"d\"e\\\"\"\"\" x <<EOF\n EOFX\n EOF;\n<<'A'\nA x<1"), expect![[r#"
+            StringLit 0..7 "'''a'''"
+            StringLit 8..17 "'''b'c'''"
+            StringLit 18..29 "\"\"\"d\"e\\\"\"\"\""
+            Ident 30..31 "x"
+            StringLit 32..48 "<<EOF\n EOFX\n EOF"
+            Punct 48..49 ";"
+            StringLit 50..57 "<<'A'\nA"
+            Ident 58..59 "x"
+            Punct 59..60 "<"
+            Number 60..61 "1"
+        "#]]);
+    }
+
+    #[test]
+    fn multi_line_unterminated() {
+        let srcs = [
+            "print '''a\n''",
+            "print \"\"\"a\n\"\"",
+            "print \"\"\"${\n\"\"\"",
+            "print <<EOF\na\n EOFX\n",
+            "print <<EOF;\nEOF",
+            "print <<'EOF\nEOF",
+            "print <<'EOF测'\nEOF",
+            "1$-<<'9é.'",
+            "print <<EOF\n${1\nEOF",
+        ];
+        let actual: String = srcs.iter()
+            .map(|src| Lexer::new(src).find_map(Result::err).unwrap().to_string() + "\n")
+            .collect();
+        expect![[r#"
+            error[E0002]: String literal not terminated at 1:7 `'''a`
+            error[E0002]: String literal not terminated at 1:7 `"""a`
+            error[E0002]: String interpolation not terminated at 1:10 `${`
+            error[E0002]: Heredoc `<<EOF` not terminated by a line of `EOF` at 1:7 `<<EOF`
+            error[E0002]: Expected a newline after `<<EOF` at 1:7 `<<EOF;`
+            error[E0002]: Unterminated heredoc header, expected a marker like `<<EOF` or `<<'EOF'` at 1:7 `<<'EOF`
+            error[E0002]: Unterminated heredoc header, expected a marker like `<<EOF` or `<<'EOF'` at 1:7 `<<'EOF测'`
+            error[E0002]: Unterminated heredoc header, expected a marker like `<<EOF` or `<<'EOF'` at 1:4 `<<'9é.'`
+            error[E0002]: String interpolation not terminated at 2:1 `${1`
+        "#]].assert_eq(&actual);
+    }
+}
+
+mod highlight {
+    use super::*;
+    use crate::highlight::{Format, highlight};
+
+    #[test]
+    fn html() {
+        let src = "if x<1 { print \"a\\n\" + 'b\\n'; } // <c>\nx = null; @";
+        expect![[r#"
+            <pre class="rsd"><code><span class="keyword">if</span> x<span class="operator">&lt;</span><span class="number">1</span> <span class="punct">{</span> <span class="keyword">print</span> <span class="string">&quot;a</span><span class="escape">\n</span><span class="string">&quot;</span> <span class="operator">+</span> <span class="string">'b\n'</span><span class="punct">;</span> <span class="punct">}</span> <span class="comment">// &lt;c&gt;</span>
+            x <span class="operator">=</span> <span class="keyword">null</span><span class="punct">;</span> <span class="error">@</span></code></pre>
+        "#]].assert_eq(&highlight(src, Format::Html));
+    }
+
+    #[test]
+    fn ansi() {
+        let src = "while 2 { print -x; }";
+        expect![[r#""\u{1b}[1;35mwhile\u{1b}[0m \u{1b}[36m2\u{1b}[0m { \u{1b}[1;35mprint\u{1b}[0m \u{1b}[1m-\u{1b}[0mx; }""#]].assert_eq(&format!("{:?}", highlight(src, Format::Ansi)));
+    }
+
+    #[test]
+    fn interpolation() {
+        let src = r#"print "a ${x + "\n"} $5 \$\u{1F600}\x41";"#;
+        expect![[r#"
+            <pre class="rsd"><code><span class="keyword">print</span> <span class="string">&quot;a </span><span class="escape">${</span>x <span class="operator">+</span> <span class="string">&quot;</span><span class="escape">\n</span><span class="string">&quot;</span><span class="escape">}</span><span class="string"> $5 </span><span class="escape">\$</span><span class="escape">\u{1F600}</span><span class="escape">\x41</span><span class="string">&quot;</span><span class="punct">;</span></code></pre>
+        "#]].assert_eq(&highlight(src, Format::Html));
+    }
+
+    #[test]
+    fn multi_line_strings() {
+        let src = "print <<EOF\n  a ${x}\\t\n  EOF + <<'R'\n${x}\nR + '''\n'\n''';";
+        expect![[r#"
+            <pre class="rsd"><code><span class="keyword">print</span> <span class="string">&lt;&lt;EOF
+              a </span><span class="escape">${</span>x<span class="escape">}</span><span class="escape">\t</span><span class="string">
+              EOF</span> <span class="operator">+</span> <span class="string">&lt;&lt;'R'
+            ${x}
+            R</span> <span class="operator">+</span> <span class="string">'''
+            '
+            '''</span><span class="punct">;</span></code></pre>
+        "#]].assert_eq(&highlight(src, Format::Html));
+    }
+
+    #[test]
+    fn unterminated() {
+        let src = "print 1; print 'a\nb";
+        expect![[r#"
+            <pre class="rsd"><code><span class="keyword">print</span> <span class="number">1</span><span class="punct">;</span> <span class="keyword">print</span> <span class="error">'a
+            b</span></code></pre>
+        "#]].assert_eq(&highlight(src, Format::Html));
+        let src = "print \"abc${ 1;";
+        expect![[r#"
+            <pre class="rsd"><code><span class="keyword">print</span> <span class="error">&quot;abc${ 1;</span></code></pre>
+        "#]].assert_eq(&highlight(src, Format::Html));
+    }
+
+    #[test]
+    fn lossless() {
+        let srcs = [
+            "print \"abc${ 1;",
+            "x = 1; print \"a${\"b${x\"}\";",
+            "print <<EOF\n${x\n",
+            "print \"\\u{zz}\" + 1;",
+            "print 0x; // c",
+            "print '''a",
+        ];
+        for src in srcs {
+            let out = highlight(src, Format::Ansi);
+            let mut plain = String::new();
+            let mut parts = out.split('\x1b');
+            plain.push_str(parts.next().unwrap());
+            for part in parts {
+                plain.push_str(&part[part.find('m').unwrap()+1..]);
+            }
+            assert_eq!(plain, src);
+        }
+    }
+}
+
+mod builtins {
+    use super::*;
+
+    /// Host with an environment, and in-memory files only allowed under `sandbox/`
+    #[derive(Debug, Default)]
+    struct Host {
+        out: String,
+        files: std::collections::BTreeMap<String, String>,
+    }
+    impl Host {
+        fn sandbox(&self, path: &str) -> Result<(), String> {
+            match path.strip_prefix("sandbox/") {
+                Some(name) if !name.split('/').any(|part| part == "..") => Ok(()),
+                _ => Err(format!("Access denied: {path}")),
+            }
+        }
+    }
+    impl Config for Host {
+        fn print(&mut self, args: fmt::Arguments<'_>) {
+            fmt::write(&mut self.out, args).unwrap();
+        }
+
+        fn env(&self, name: &str) -> Option<String> {
+            (name == "USER").then(|| "tester".to_owned())
+        }
+
+        fn read_file(&mut self, path: &str) -> Result<String, String> {
+            self.sandbox(path)?;
+            self.files.get(path).cloned().ok_or_else(|| format!("Not found: {path}"))
+        }
+
+        fn write_file(&mut self, path: &str, contents: &str) -> Result<(), String> {
+            self.sandbox(path)?;
+            self.files.insert(path.to_owned(), contents.to_owned());
+            Ok(())
+        }
+
+        fn append_file(&mut self, path: &str, contents: &str) -> Result<(), String> {
+            self.sandbox(path)?;
+            self.files.entry(path.to_owned()).or_default().push_str(contents);
+            Ok(())
+        }
+
+        fn exists(&mut self, path: &str) -> Result<bool, String> {
+            self.sandbox(path)?;
+            Ok(self.files.contains_key(path))
+        }
+    }
+
+    #[track_caller]
+    fn check(src: &str, args: &[&str], expect: Expect) {
+        let mut rt = Rt::with_config(Host::default());
+        rt.load_source(src);
+        rt.set_args(args.iter().copied());
+        rt.check();
+        rt.proc();
+        expect.assert_eq(&rt.cfg.out);
+    }
+
+    #[track_caller]
+    fn check_files(src: &str, expect: Expect) {
+        let mut rt = Rt::with_config(Host::default());
+        rt.cfg.files.insert("sandbox/in.txt".to_owned(), "a\nb\n".to_owned());
+        rt.load_source(src);
+        rt.check();
+        rt.proc();
+        let mut actual = rt.cfg.out;
+        for (path, contents) in &rt.cfg.files {
+            actual += &format!("--- {path}\n{contents}");
+        }
+        expect.assert_eq(&actual);
+    }
+
+    #[test]
+    fn files() {
+        check_files(r#"
+            s = read_file('sandbox/in.txt');
+            write_file('sandbox/out.txt', s + "c\n");
+            append_file('sandbox/out.txt', "d\n");
+            append_file('sandbox/log.txt', "x\n");
+            print exists('sandbox/out.txt');
+            print exists('sandbox/none.txt');
+        "#, expect![[r#"
+            1
+            NULL
+            --- sandbox/in.txt
+            a
+            b
+            --- sandbox/log.txt
+            x
+            --- sandbox/out.txt
+            a
+            b
+            c
+            d
+        "#]]);
+        check_files("if null { write_file('/etc/passwd', ''); }", expect![[r#"
+            --- sandbox/in.txt
+            a
+            b
+        "#]]);
+    }
+
+    #[test]
+    fn files_denied_by_default() {
+        check_error_json("print exists('a');", expect![[r#"{"severity":"error","code":"E0014","message":"File access is not allowed","file":"test.rsd","byte_start":6,"byte_end":17,"line_start":1,"column_start":7,"line_end":1,"column_end":18,"preview":"exists('a');"}"#]]);
+    }
+
+    #[test]
+    #[should_panic = "error[E0014]: Access denied: sandbox/../x at 1:1 `write_file('sandbox/../x', 'a');`"]
+    fn files_denied() {
+        check_files("write_file('sandbox/../x', 'a');", expect![""]);
+    }
+
+    #[test]
+    #[should_panic = "error[E0014]: Not found: sandbox/none at 1:7"]
+    fn file_not_found() {
+        check_files("print read_file('sandbox/none');", expect![""]);
+    }
+
+    #[test]
+    #[should_panic = "error[E0013]: Function `write_file` takes 2 arguments, but 1 given"]
+    fn write_file_arguments() {
+        check_files("if null { write_file('sandbox/x'); }", expect![""]);
+    }
+
+    #[test]
+    fn env() {
+        check("print env('USER'); print env ( \"HOME\" );", &[], expect![[r#"
+            tester
+            NULL
+        "#]]);
+        check("print 'user: ' + env('US' + 'ER');", &[], expect![[r#"
+            user: tester
+        "#]]);
+        check("if null { print env('USER'); }", &[], expect![""]);
+    }
+
+    #[test]
+    fn env_sandboxed_by_default() {
+        super::check("print env('HOME');", expect![[r#"
+            NULL
+        "#]]);
+    }
+
+    #[test]
+    fn args() {
+        check("print argc; print arg0; print arg1;", &["a", "b c"], expect![[r#"
+            2
+            a
+            b c
+        "#]]);
+        check("print argc;", &[], expect![[r#"
+            0
+        "#]]);
+    }
+
+    #[test]
+    #[should_panic = "error[E0004]: Unknown variable `arg0`"]
+    fn missing_arg() {
+        check("print arg0;", &[], expect![""]);
+    }
+
+    #[track_caller]
+    fn check_input(src: &str, input: &str, expect: Expect) {
+        let mut rt = Rt::with_config(Output::default());
+        rt.cfg.input = input.split_inclusive('\n').map(str::to_owned).collect();
+        rt.load_source(src);
+        rt.check();
+        rt.proc();
+        expect.assert_eq(&rt.cfg.out);
+    }
+
+    #[test]
+    fn input() {
+        check_input("print input(); print input();", "a\nb c\n", expect![[r#"
+            a
+            b c
+        "#]]);
+        check_input("print input() + '|';", "crlf\r\n", expect![[r#"
+            crlf|
+        "#]]);
+        check_input("print input(); print input();", "no newline", expect![[r#"
+            no newline
+            NULL
+        "#]]);
+        check_input("print input() == null;", "", expect![[r#"
+            1
+        "#]]);
+        check_input("print input() == null;", "\n", expect![[r#"
+            NULL
+        "#]]);
+        check_input(r#"
+            n = 0;
+            line = input();
+            while line != null {
+                n = n + 1;
+                print line + ' #' + n;
+                line = input();
+            }
+        "#, "x\ny\n", expect![[r#"
+            x #1
+            y #2
+        "#]]);
+    }
+
+    #[test]
+    fn input_skipped() {
+        check_input("if null { print input(); } print input();", "a\n", expect![[r#"
+            a
+        "#]]);
+    }
+
+    #[test]
+    fn clock() {
+        super::check("print now();", expect![[r#"
+            1700000000
+        "#]]);
+        super::check(r#"
+            start = now();
+            sleep(1.5);
+            sleep(-1);
+            sleep(null);
+            i = 0;
+            while i < 3 {
+                sleep(0.25);
+                i = i + 1;
+            }
+            print now() - start;
+            if null { sleep(100); }
+            print now() - start;
+        "#, expect![[r#"
+            2.25
+            2.25
+        "#]]);
+    }
+
+    #[test]
+    #[should_panic = "error[E0012]: Unknown function `length`"]
+    fn unknown_function() {
+        check("if null { print length('a'); }", &[], expect![""]);
+    }
+
+    #[test]
+    #[should_panic = "error[E0013]: Function `env` takes 1 argument, but 2 given at 1:7 `env('a', 'b');`"]
+    fn argument_count() {
+        check("print env('a', 'b');", &[], expect![""]);
+    }
+
+    #[test]
+    #[should_panic = "error[E0007]: Expected a `,` or `)`"]
+    fn missing_comma() {
+        check("print env('a' 'b');", &[], expect![""]);
+    }
+}
+
+mod lines {
+    use super::*;
+
+    #[track_caller]
+    fn check(src: &str, mode: LineMode, input: &str, expect: Expect) {
+        let mut rt = Rt::with_config(Output::default());
+        rt.cfg.input = input.split_inclusive('\n').map(str::to_owned).collect();
+        rt.load_source(src);
+        rt.set_line_mode(mode);
+        rt.check();
+        let status = rt.proc();
+        assert_eq!(rt.ignore_level, 0, "Not cleanly effects");
+        expect.assert_eq(&format!("{status:?}\n{}", rt.cfg.out));
+    }
+
+    #[test]
+    fn each() {
+        check("print nr; print line;", LineMode::Each, "a\nb\n", expect![[r#"
+            Done
+            1
+            a
+            2
+            b
+        "#]]);
+        check("print line;", LineMode::Each, "", expect![[r#"
+            Done
+        "#]]);
+        check("", LineMode::Each, "a\n", expect![[r#"
+            Done
+        "#]]);
+    }
+
+    #[test]
+    fn print() {
+        check("line = line + '!';", LineMode::Print, "a\nb", expect![[r#"
+            Done
+            a!
+            b!
+        "#]]);
+        check("if line == 'b' { line = null; }", LineMode::Print, "a\nb\nc\n", expect![[r#"
+            Done
+            a
+            NULL
+            c
+        "#]]);
+    }
+
+    #[test]
+    fn begin_end() {
+        check(r#"
+            BEGIN { total = 0; print 'begin'; }
+            total = total + -line;
+            END { print total; print nr; }
+        "#, LineMode::Each, "ab\ncde\n", expect![[r#"
+            Done
+            begin
+            5
+            2
+        "#]]);
+        check("BEGIN { print 1; } END { print 2; }", LineMode::Print, "", expect![[r#"
+            Done
+            1
+            2
+        "#]]);
+        check("BEGIN { print 1; } END { print 2; }", LineMode::Print, "x\n", expect![[r#"
+            Done
+            1
+            x
+            2
+        "#]]);
+        check("BEGIN = 1; END = BEGIN; print END;", LineMode::Each, "x\n", expect![[r#"
+            Done
+            1
+        "#]]);
+    }
+
+    #[test]
+    fn exit() {
+        check(r#"
+            if line == 'stop' { exit nr; }
+            print line;
+            END { print 'end'; }
+        "#, LineMode::Print, "a\nstop\nb\n", expect![[r#"
+            Exit(2)
+            a
+            a
+        "#]]);
+    }
+
+    #[test]
+    fn exit_in_begin() {
+        check(r#"
+            BEGIN { exit 3; }
+            print line;
+            END { print 'end'; }
+        "#, LineMode::Each, "a\n", expect![[r#"
+            Exit(3)
+        "#]]);
+    }
+
+    #[test]
+    #[should_panic = "error[E0004]: Unknown variable `last`"]
+    fn no_lines_before_end() {
+        check("last = line; END { print last; }", LineMode::Each, "", expect![""]);
+    }
+
+    #[test]
+    #[should_panic = "error[E0007]: Expected a `=`"]
+    fn sections_need_line_mode() {
+        run("BEGIN { print 1; }");
+    }
+
+    #[test]
+    #[should_panic = "error[E0007]: Expected a `=`"]
+    fn begin_after_body() {
+        check("print line; BEGIN { }", LineMode::Each, "", expect![""]);
+    }
+}
+
+mod random {
+    use super::*;
+    use crate::random::Rng;
+
+    #[test]
+    fn splitmix64_reference() {
+        let mut rng = Rng::new(0);
+        assert_eq!(rng.next_u64(), 0xe220_a839_7b1d_cdaf);
+        assert_eq!(rng.next_u64(), 0x6e78_9e6a_a1b9_65f4);
+        assert_eq!(rng.next_u64(), 0x06c4_5d18_8009_454f);
+    }
+
+    #[test]
+    fn bounds() {
+        let mut rng = Rng::new(1);
+        for _ in 0..1000 {
+            assert!((0.0..1.0).contains(&rng.next_f64()));
+            assert!(rng.below_or_eq(5) <= 5);
+        }
+        assert_eq!(rng.below_or_eq(0), 0);
+    }
+
+    #[test]
+    fn builtins() {
+        check(r#"
+            print random();
+            print random_int(1, 6);
+            print random_int(-2.5, 2.5);
+            print random_int(7, 7);
+            i = 0;
+            while i < 100 {
+                n = random_int(1, 3);
+                if n < 1 || n > 3 { print 'out of range: ' + n; }
+                i = i + 1;
+            }
+        "#, expect![[r#"
+            0.21577991816197029
+            2
+            -2
+            7
+        "#]]);
+        check("if null { print random(); } print random();", expect![[r#"
+            0.21577991816197029
+        "#]]);
+    }
+
+    #[test]
+    fn same_seed() {
+        let src = "print random(); print random_int(0, 1000000);";
+        assert_eq!(run(src).cfg.out, run(src).cfg.out);
+    }
+
+    #[test]
+    #[should_panic = "error[E0015]: Invalid range 3 to 2 for `random_int`"]
+    fn empty_range() {
+        run("print random_int(2.5, 2.9);");
+    }
+}
+
+mod number_format {
+    use super::*;
+
+    #[track_caller]
+    fn check_fmt(cases: &[(f64, &str)], expect: Expect) {
+        let mut actual = String::new();
+        for &(n, spec) in cases {
+            let format = NumberFormat::parse(spec).unwrap();
+            actual += &format!("{n:?} {spec:?} => {:?}\n", format.value(&Value::Number(n)).unwrap());
+        }
+        expect.assert_eq(&actual);
+    }
+
+    #[test]
+    fn fixed_and_precision() {
+        check_fmt(&[
+            (0.1 + 0.2, ""),
+            (0.1 + 0.2, ".2f"),
+            (0.1 + 0.2, ".3"),
+            (2.5, ".0f"),
+            (3.5, ".0f"),
+            (1.0, "f"),
+            (0.125, ".1%"),
+            (1e21, ""),
+            (1e21, ".2f"),
+        ], expect![[r#"
+            0.30000000000000004 "" => "0.30000000000000004"
+            0.30000000000000004 ".2f" => "0.30"
+            0.30000000000000004 ".3" => "0.3"
+            2.5 ".0f" => "2"
+            3.5 ".0f" => "4"
+            1.0 "f" => "1.000000"
+            0.125 ".1%" => "12.5%"
+            1e21 "" => "1000000000000000000000"
+            1e21 ".2f" => "1000000000000000000000.00"
+        "#]]);
+    }
+
+    #[test]
+    fn exponent_and_general() {
+        check_fmt(&[
+            (1234.5, "e"),
+            (1234.5, ".2E"),
+            (0.00012, ".1e"),
+            (1e100, "e"),
+            (0.0, "e"),
+            (1234.5, "g"),
+            (123456789.0, "g"),
+            (0.0001, "g"),
+            (0.00001, "g"),
+            (9.9999, ".2g"),
+            (100.0, ".0g"),
+            (1e21, ".3"),
+        ], expect![[r#"
+            1234.5 "e" => "1.234500e+03"
+            1234.5 ".2E" => "1.23E+03"
+            0.00012 ".1e" => "1.2e-04"
+            1e100 "e" => "1.000000e+100"
+            0.0 "e" => "0.000000e+00"
+            1234.5 "g" => "1234.5"
+            123456789.0 "g" => "1.23457e+08"
+            0.0001 "g" => "0.0001"
+            1e-5 "g" => "1e-05"
+            9.9999 ".2g" => "10"
+            100.0 ".0g" => "1e+02"
+            1e21 ".3" => "1e+21"
+        "#]]);
+    }
+
+    #[test]
+    fn width_align_sign() {
+        check_fmt(&[
+            (42.0, "6"),
+            (42.0, "<6"),
+            (42.0, "^6"),
+            (42.0, "*^7"),
+            (-42.0, "=6"),
+            (-42.0, "06"),
+            (42.0, "+"),
+            (42.0, " "),
+            (-42.0, " "),
+            (42.0, "+08.2f"),
+            (42.0, "x<06"),
+            (42.0, "1"),
+        ], expect![[r#"
+            42.0 "6" => "    42"
+            42.0 "<6" => "42    "
+            42.0 "^6" => "  42  "
+            42.0 "*^7" => "**42***"
+            -42.0 "=6" => "-   42"
+            -42.0 "06" => "-00042"
+            42.0 "+" => "+42"
+            42.0 " " => " 42"
+            -42.0 " " => "-42"
+            42.0 "+08.2f" => "+0042.00"
+            42.0 "x<06" => "42xxxx"
+            42.0 "1" => "42"
+        "#]]);
+    }
+
+    #[test]
+    fn grouping() {
+        check_fmt(&[
+            (1234567.0, ","),
+            (1234567.891, ",.2f"),
+            (-1234.0, "_"),
+            (123.0, ","),
+            (1234567.0, "015,"),
+            (12345.678, ",e"),
+            (0.5, ",%"),
+        ], expect![[r#"
+            1234567.0 "," => "1,234,567"
+            1234567.891 ",.2f" => "1,234,567.89"
+            -1234.0 "_" => "-1_234"
+            123.0 "," => "123"
+            1234567.0 "015," => "0000001,234,567"
+            12345.678 ",e" => "1.234568e+04"
+            0.5 ",%" => "50.000000%"
+        "#]]);
+    }
+
+    #[test]
+    fn special_values() {
+        check_fmt(&[
+            (f64::NAN, ""),
+            (f64::NAN, ".2f"),
+            (f64::NAN, "+"),
+            (f64::NAN, "06"),
+            (f64::INFINITY, ""),
+            (f64::INFINITY, "+.2e"),
+            (f64::NEG_INFINITY, "08,"),
+            (f64::NEG_INFINITY, "%"),
+            (-0.0, ""),
+            (-0.0, ".2f"),
+            (-0.0, "+g"),
+            (0.0, "+g"),
+            (-0.001, ".2f"),
+        ], expect![[r#"
+            NaN "" => "NaN"
+            NaN ".2f" => "NaN"
+            NaN "+" => "+NaN"
+            NaN "06" => "   NaN"
+            inf "" => "inf"
+            inf "+.2e" => "+inf"
+            -inf "08," => "    -inf"
+            -inf "%" => "-inf"
+            -0.0 "" => "-0"
+            -0.0 ".2f" => "-0.00"
+            -0.0 "+g" => "-0"
+            0.0 "+g" => "+0"
+            -0.001 ".2f" => "-0.00"
+        "#]]);
+    }
+
+    #[test]
+    fn invalid_specs() {
+        let specs = ["x", "5x", ".", ".f", "<<<", "+-", "5.2ff", ",,", ".70000f", "99999999999999999999999", "10001"];
+        let actual: String = specs.iter()
+            .map(|spec| format!("{spec:?}: {}\n", NumberFormat::parse(spec).unwrap_err()))
+            .collect();
+        expect![[r#"
+            "x": Unexpected `x` in format spec "x"
+            "5x": Unexpected `x` in format spec "5x"
+            ".": Expected precision after `.`
+            ".f": Expected precision after `.`
+            "<<<": Unexpected `<` in format spec "<<<"
+            "+-": Unexpected `-` in format spec "+-"
+            "5.2ff": Unexpected `f` in format spec "5.2ff"
+            ",,": Unexpected `,` in format spec ",,"
+            ".70000f": Format spec precision 70000 too large, at most 10000
+            "99999999999999999999999": Format spec width 99999999999999999999999 too large, at most 10000
+            "10001": Format spec width 10001 too large, at most 10000
+        "#]].assert_eq(&actual);
+    }
+
+    #[test]
+    fn builtin() {
+        check(r#"
+            print fmt(0.1 + 0.2, '.2f');
+            print '[' + fmt('ab', '^6') + ']';
+            print '[' + fmt('abcdef', '.3') + ']';
+            print '[' + fmt(null, '>6') + ']';
+            print '[' + fmt(12, 's') + ']';
+            if null { print fmt(1, 'invalid'); }
+        "#, expect![[r#"
+            0.30
+            [  ab  ]
+            [abc]
+            [  NULL]
+            [12]
+        "#]]);
+    }
+
+    #[test]
+    #[should_panic = "error[E0015]: Unexpected `x` in format spec \"5x\""]
+    fn builtin_invalid_spec() {
+        run("print fmt(1, '5x');");
+    }
+
+    #[test]
+    fn large_width_and_precision() {
+        let format = NumberFormat::parse("10000.10000g").unwrap();
+        for n in [1.0, 1e-4, 1e300, f64::MIN_POSITIVE] {
+            assert_eq!(format.apply(&Value::Number(n)).len(), 10_000);
+        }
+        for spec in [".10000f", ".10000e", ".10000%"] {
+            NumberFormat::parse(spec).unwrap().apply(&Value::Number(-1e300));
+        }
+    }
+
+    #[test]
+    #[should_panic = "error[E0015]: Format spec precision 70000 too large, at most 10000"]
+    fn builtin_large_precision() {
+        run("print fmt(1, '.70000f');");
+    }
+
+    #[test]
+    #[should_panic = "error[E0015]: Number format spec used for string"]
+    fn builtin_number_spec_for_string() {
+        run("print fmt('a', '.2f');");
+    }
+
+    #[test]
+    fn config() {
+        struct Fixed(String);
+        impl Config for Fixed {
+            fn print(&mut self, args: fmt::Arguments<'_>) {
+                fmt::write(&mut self.0, args).unwrap();
+            }
+
+            fn number_format(&self) -> NumberFormat {
+                NumberFormat::parse(",.2f").unwrap()
+            }
+        }
+        let mut rt = Rt::with_config(Fixed(String::new()));
+        rt.load_source("print 1234.5; print 'a' + 0.5; write 1/3; print null;");
+        rt.proc();
+        expect![[r#"
+            1,234.50
+            a0.5
+            0.33NULL
+        "#]].assert_eq(&rt.cfg.0);
+    }
+}
+
+mod number_literals {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let literals = [
+            "0", "007", "2.5", "1.", ".5", "1_000_000", "0.000_1", "1e6", "1E-3", "2.5e+2", "1e21",
+            "1e-7", "0xFF", "0Xff", "0x1F_FF", "0b1010", "0B1_0000_0000", "0x1fffffffffffff",
+        ];
+        let mut actual = String::new();
+        for literal in literals {
+            let printed = run(&format!("print {literal};")).cfg.out;
+            let printed = printed.trim_end();
+            let same = run(&format!("print {printed} == {literal};")).cfg.out;
+            actual += &format!("{literal} => {printed} {}", same);
+        }
+        expect![[r#"
+            0 => 0 1
+            007 => 7 1
+            2.5 => 2.5 1
+            1. => 1 1
+            .5 => 0.5 1
+            1_000_000 => 1000000 1
+            0.000_1 => 0.0001 1
+            1e6 => 1000000 1
+            1E-3 => 0.001 1
+            2.5e+2 => 250 1
+            1e21 => 1000000000000000000000 1
+            1e-7 => 0.0000001 1
+            0xFF => 255 1
+            0Xff => 255 1
+            0x1F_FF => 8191 1
+            0b1010 => 10 1
+            0B1_0000_0000 => 256 1
+            0x1fffffffffffff => 9007199254740991 1
+        "#]].assert_eq(&actual);
+    }
+
+    #[test]
+    fn tokens() {
+        check_tokens(Lexer::new("1e-5+.5-0xE-1.2.3 2else"), expect![[r#"
+            Number 0..4 "1e-5"
+            Punct 4..5 "+"
+            Number 5..7 ".5"
+            Punct 7..8 "-"
+            Number 8..11 "0xE"
+            Punct 11..12 "-"
+            Number 12..17 "1.2.3"
+            Number 18..23 "2else"
+        "#]]);
+    }
+
+    #[test]
+    fn invalid() {
+        let literals = [
+            "1.2.3", "1.5e3.2", "1e", "1e+", "0x", "0b102", "0xG", "12abc", "0x_1", "1__0", "1_", "0b1.1",
+        ];
+        let mut actual = String::new();
+        for literal in literals {
+            let src = format!("print {literal};");
+            let err = std::panic::catch_unwind(|| run(&src)).unwrap_err();
+            actual += err.downcast_ref::<String>().unwrap();
+            actual += "\n";
+        }
+        expect![[r#"
+            error[E0005]: Unexpected second `.` in number at 1:10 `.3;`
+            error[E0005]: Unexpected `.` in exponent at 1:12 `.2;`
+            error[E0005]: Expected digits in exponent at 1:8 `e;`
+            error[E0005]: Expected digits in exponent at 1:8 `e+;`
+            error[E0005]: Expected digits after `0x` at 1:7 `0x;`
+            error[E0005]: Invalid character `2` in binary number at 1:11 `2;`
+            error[E0005]: Invalid character `G` in hexadecimal number at 1:9 `G;`
+            error[E0005]: Invalid character `a` in decimal number at 1:9 `abc;`
+            error[E0005]: Misplaced `_` in number, only allowed between digits at 1:9 `_1;`
+            error[E0005]: Misplaced `_` in number, only allowed between digits at 1:8 `__0;`
+            error[E0005]: Misplaced `_` in number, only allowed between digits at 1:8 `_;`
+            error[E0005]: Invalid character `.` in binary number at 1:10 `.1;`
+        "#]].assert_eq(&actual);
+    }
+
+    #[test]
+    #[should_panic = "error[E0005]: Unexpected second `.` in number at 3:30"]
+    fn invalid_in_skipped_branch() {
+        check_warnings(r#"
+            x = 1;
+            if null { x = 1.2.3; }
+        "#, expect![""]);
+    }
+
+    #[test]
+    fn constants() {
+        check(r#"
+            print inf; print -inf; print nan;
+            print 1 / 0 == inf; print inf > 1e308;
+            print fmt(nan, '+.2f'); print 'x' + inf;
+        "#, expect![[r#"
+            inf
+            -inf
+            NaN
+            1
+            1
+            +NaN
+            xinf
+        "#]]);
+    }
+}
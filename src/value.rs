@@ -1,4 +1,6 @@
-use std::{cmp::Ordering::*, fmt};
+use std::{cmp::Ordering::*, fmt, mem};
+
+use crate::{Code, lexer::parse_number};
 
 fn unum(n: f64) -> usize {
     if n.is_nan() {
@@ -7,6 +9,7 @@ fn unum(n: f64) -> usize {
     n.clamp(usize::MIN as f64, usize::MAX as f64).floor() as usize
 }
 
+/// Value of expressions and variables, `null` is the only false value
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     Number(f64),
@@ -35,10 +38,55 @@ impl From<bool> for Value {
 }
 
 impl Value {
+    /// Value of a command line definition, a number if `s` is a number literal
+    /// such as `0x10` or `-1e3`, otherwise a string
+    pub fn parse_define(s: &str) -> Self {
+        let (sign, digits) = match s.strip_prefix('-') {
+            Some(digits) => (-1.0, digits),
+            None => (1.0, s),
+        };
+        match parse_number(digits) {
+            Ok(n) => Value::Number(sign * n),
+            Err(_) => Value::String(s.to_owned()),
+        }
+    }
+
     pub(crate) fn bool(&self) -> bool {
         !matches!(self, Value::Null)
     }
 
+    pub(crate) fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Null => "null",
+        }
+    }
+
+    /// Find binary operations that silently coerce an operand
+    pub(crate) fn lossy_op(&self, op: &str, rhs: &Self) -> Option<Code> {
+        let same_type = mem::discriminant(self) == mem::discriminant(rhs);
+        match (self, op, rhs) {
+            (_, "<" | "<=" | ">" | ">=", _) if !same_type => {
+                Some(Code::MixedComparison)
+            },
+            (Value::Number(_), "+" | "-" | "*" | "/" | "%", Value::String(_))
+            | (Value::String(_), "-", Value::Number(_))
+            | (Value::String(_), "*" | "/" | "%", Value::String(_) | Value::Null)
+            => Some(Code::StringCoercion),
+            _ => None,
+        }
+    }
+
+    /// Binary operations that are rejected in strict mode
+    pub(crate) fn strict_rejects(&self, op: &str, rhs: &Self) -> bool {
+        !matches!((self, op, rhs),
+            | (_, "==" | "!=" | "&&" | "||", _)
+            | (Value::Number(_), _, Value::Number(_))
+            | (Value::String(_), "+" | "-" | "<" | "<=" | ">" | ">=", Value::String(_))
+            | (Value::Null, "<" | "<=" | ">" | ">=", Value::Null))
+    }
+
     pub(crate) fn num(&self, unit: i8) -> f64 {
         match self {
             Value::Number(n) => *n,
@@ -0,0 +1,533 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use expect_test::{Expect, expect};
+
+/// Run the binary with arguments and stdin, snapshot exit code and both streams
+#[track_caller]
+fn check_cli(args: &[&str], stdin: &str, expect: Expect) {
+    check_cli_in(env!("CARGO_MANIFEST_DIR"), args, stdin, expect);
+}
+
+#[track_caller]
+fn check_cli_in(dir: impl AsRef<Path>, args: &[&str], stdin: &str, expect: Expect) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_run-str-demo"))
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // the program may exit before reading all of stdin
+    if let Err(e) = child.stdin.take().unwrap().write_all(stdin.as_bytes()) {
+        assert_eq!(e.kind(), io::ErrorKind::BrokenPipe, "{e}");
+    }
+    let output = child.wait_with_output().unwrap();
+    let actual = format!(
+        "status: {:?}\nstdout: {}\nstderr: {}",
+        output.status.code(),
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr),
+    );
+    expect.assert_eq(&actual);
+}
+
+#[test]
+fn eval() {
+    check_cli(&["-e", "print 1 + 2;"], "", expect![[r#"
+        status: Some(0)
+        stdout: 3
+
+        stderr: "#]]);
+    check_cli(&["--eval=print 'a';"], "", expect![[r#"
+        status: Some(0)
+        stdout: a
+
+        stderr: "#]]);
+    check_cli(&["-e", "x = 1; print x;", "--check"], "", expect![[r#"
+        status: Some(0)
+        stdout: 
+        stderr: "#]]);
+    check_cli(&["-e", "print 'a' - 1;", "--strict"], "", expect![[r#"
+        status: Some(1)
+        stdout: 
+        stderr: <eval>: error[E0011]: Operation `-` between string and number in strict mode at 1:11 `- 1;`
+    "#]]);
+    check_cli(&["-e", "x;", "--error-format=json"], "", expect![[r#"
+        status: Some(1)
+        stdout: 
+        stderr: {"severity":"error","code":"E0007","message":"Expected a `=`","file":"<eval>","byte_start":1,"byte_end":2,"line_start":1,"column_start":2,"line_end":1,"column_end":3,"preview":";"}
+    "#]]);
+    check_cli(&["-e", "x = 1;", "-D", "warnings"], "", expect![[r#"
+        status: Some(1)
+        stdout: 
+        stderr: <eval>: error[W0001]: Variable `x` is assigned but never read at 1:1 `x = 1;`
+    "#]]);
+    check_cli(&["-e", "print 1 || missing;"], "", expect![[r#"
+        status: Some(0)
+        stdout: 1
+
+        stderr: <eval>: warning[W0006]: Variable `missing` is never assigned at 1:12 `missing;`
+    "#]]);
+    check_cli(&["-e", "print 1 || missing;", "-D", "warnings"], "", expect![[r#"
+        status: Some(1)
+        stdout: 
+        stderr: <eval>: error[W0006]: Variable `missing` is never assigned at 1:12 `missing;`
+    "#]]);
+    check_cli(&["-e", "print null || missing;"], "", expect![[r#"
+        status: Some(1)
+        stdout: 
+        stderr: <eval>: warning[W0006]: Variable `missing` is never assigned at 1:15 `missing;`
+        <eval>: error[E0004]: Unknown variable `missing` at 1:15 `missing;`
+    "#]]);
+}
+
+#[test]
+fn stdin() {
+    check_cli(&["-"], "print 'from stdin';", expect![[r#"
+        status: Some(0)
+        stdout: from stdin
+
+        stderr: "#]]);
+    check_cli(&["--check", "-"], "print y;", expect![[r#"
+        status: Some(0)
+        stdout: 
+        stderr: -: warning[W0006]: Variable `y` is never assigned at 1:7 `y;`
+    "#]]);
+    check_cli(&["fmt", "-"], "print   1;x=2;", expect![[r#"
+        status: Some(0)
+        stdout: print 1;
+        x = 2;
+
+        stderr: "#]]);
+    check_cli(&["fmt", "--check", "-"], "print 1;\n", expect![[r#"
+        status: Some(0)
+        stdout: 
+        stderr: "#]]);
+    check_cli(&["highlight", "-"], "print 1;", expect![[r#"
+        status: Some(0)
+        stdout: [1;35mprint[0m [36m1[0m;
+        stderr: "#]]);
+}
+
+#[test]
+fn eval_with_subcommands() {
+    check_cli(&["fmt", "-e", "print   1;"], "", expect![[r#"
+        status: Some(0)
+        stdout: print 1;
+
+        stderr: "#]]);
+    check_cli(&["fmt", "--check", "-e", "print   1;"], "", expect![[r#"
+        status: Some(1)
+        stdout: 
+        stderr: Would reformat: <eval>
+    "#]]);
+    check_cli(&["highlight", "--format=html", "-e", "print 1;"], "", expect![[r#"
+        status: Some(0)
+        stdout: <pre class="rsd"><code><span class="keyword">print</span> <span class="number">1</span><span class="punct">;</span></code></pre>
+
+        stderr: "#]]);
+}
+
+#[test]
+fn invalid_arguments() {
+    check_cli(&[], "", expect![[r#"
+        status: Some(2)
+        stdout: 
+        stderr: Expected <prog> position argument
+    "#]]);
+    check_cli(&["-e", "print 1;", "examples/hello_world.rsd"], "", expect![[r#"
+        status: Some(2)
+        stdout: 
+        stderr: Unexpected <prog> argument with `-e`: "examples/hello_world.rsd"
+    "#]]);
+    check_cli(&["-e", "print 1;", "-"], "", expect![[r#"
+        status: Some(2)
+        stdout: 
+        stderr: Unexpected <prog> argument with `-e`: "-"
+    "#]]);
+    check_cli(&["-e"], "", expect![[r#"
+        status: Some(2)
+        stdout: 
+        stderr: Argument to option 'e' missing
+    "#]]);
+    check_cli(&["-e", "print 1;", "-e", "print 2;"], "", expect![[r#"
+        status: Some(2)
+        stdout: 
+        stderr: Option 'eval' given more than once
+    "#]]);
+    check_cli(&["examples/hello_world.rsd", "-"], "", expect![[r#"
+        status: Some(0)
+        stdout: Hello, World!
+
+        stderr: "#]]);
+    check_cli(&["fmt", "-e", "print 1;", "examples/hello_world.rsd"], "", expect![[r#"
+        status: Some(2)
+        stdout: 
+        stderr: Unexpected <prog> argument with `-e`: "examples/hello_world.rsd"
+    "#]]);
+    check_cli(&["highlight", "-e", "print 1;", "-"], "", expect![[r#"
+        status: Some(2)
+        stdout: 
+        stderr: Unexpected <prog> argument with `-e`: "-"
+    "#]]);
+    check_cli(&["lsp", "-e", "print 1;"], "", expect![[r#"
+        status: Some(2)
+        stdout: 
+        stderr: Unexpected `-e` for lsp
+    "#]]);
+}
+
+#[test]
+fn script_args() {
+    check_cli(&["-", "a", "b"], "print argc; print arg0 + arg1;", expect![[r#"
+        status: Some(0)
+        stdout: 2
+        ab
+
+        stderr: "#]]);
+    check_cli(&["-e", "print argc; print arg0;", "--", "-v", "x"], "", expect![[r#"
+        status: Some(0)
+        stdout: 2
+        -v
+
+        stderr: "#]]);
+    check_cli(&["-", "a", "--", "--check"], "print arg1;", expect![[r#"
+        status: Some(0)
+        stdout: --check
+
+        stderr: "#]]);
+    check_cli(&["--check", "-", "a"], "print arg1;", expect![[r#"
+        status: Some(0)
+        stdout: 
+        stderr: -: warning[W0005]: Variable `arg1` may be used before assignment at 1:7 `arg1;`
+    "#]]);
+    check_cli(&["-", "a"], "if argc > 1 { print arg1; } print argc;", expect![[r#"
+        status: Some(0)
+        stdout: 1
+
+        stderr: -: warning[W0005]: Variable `arg1` may be used before assignment at 1:21 `arg1; } print argc;`
+    "#]]);
+    check_cli(&["-D", "warnings", "-", "a"], "if argc > 1 { print arg1; }", expect![[r#"
+        status: Some(1)
+        stdout: 
+        stderr: -: error[W0005]: Variable `arg1` may be used before assignment at 1:21 `arg1; }`
+    "#]]);
+    check_cli(&["-", "a"], "print argv;", expect![[r#"
+        status: Some(1)
+        stdout: 
+        stderr: -: warning[W0006]: Variable `argv` is never assigned at 1:7 `argv;`
+        -: error[E0004]: Unknown variable `argv` at 1:7 `argv;`
+    "#]]);
+    check_cli(&["highlight", "-e", "print 1;", "--", "a"], "", expect![[r#"
+        status: Some(2)
+        stdout: 
+        stderr: Extra argument: "a"
+    "#]]);
+    check_cli(&["fmt", "-", "--", "a"], "", expect![[r#"
+        status: Some(2)
+        stdout: 
+        stderr: Extra argument: "a"
+    "#]]);
+}
+
+#[test]
+fn env() {
+    check_cli(&["-e", "print env('CARGO_PKG_NAME');"], "", expect![[r#"
+        status: Some(0)
+        stdout: run-str-demo
+
+        stderr: "#]]);
+    check_cli(&["-e", "print env('RUN_STR_DEMO_UNSET');"], "", expect![[r#"
+        status: Some(0)
+        stdout: NULL
+
+        stderr: "#]]);
+}
+
+#[test]
+fn define() {
+    let src = "print a; print b + 1; print c; print d;";
+    check_cli(&["-D", "a=abc", "-Db=2.5", "-D", "c", "-D", "d=-3", "-"], src, expect![[r#"
+        status: Some(0)
+        stdout: abc
+        3.5
+        1
+        -3
+
+        stderr: "#]]);
+    check_cli(&["-D", "a=1x", "-D", "b=", "-D", "c=a=b", "-D", "d=1.2.3", "-"], src, expect![[r#"
+        status: Some(0)
+        stdout: 1x
+        1
+        a=b
+        1.2.3
+
+        stderr: "#]]);
+    let src = "print a + 1; print b + 1; print c + 1; print d + 1; print e + 1;";
+    check_cli(&["-D", "a=0x10", "-D", "b=1e3", "-D", "c=.5", "-D", "d=1_000", "-D", "e=-0b11", "-"], src, expect![[r#"
+        status: Some(0)
+        stdout: 17
+        1001
+        1.5
+        1001
+        -2
+
+        stderr: "#]]);
+    check_cli(&["-D", "a=-", "-D", "b=1e", "-D", "c=--1", "-D", "d=1__0", "-D", "e=inf", "-"], src, expect![[r#"
+        status: Some(0)
+        stdout: -1
+        1e1
+        --11
+        1__01
+        inf1
+
+        stderr: "#]]);
+    check_cli(&["-D", "warnings", "-D", "x=1", "-e", "print x;"], "", expect![[r#"
+        status: Some(0)
+        stdout: 1
+
+        stderr: "#]]);
+    check_cli(&["-D", "x=1", "-D", "x=2", "-e", "print x;"], "", expect![[r#"
+        status: Some(0)
+        stdout: 2
+
+        stderr: "#]]);
+    check_cli(&["-D", "argc=9", "-e", "print argc;", "--", "a"], "", expect![[r#"
+        status: Some(0)
+        stdout: 9
+
+        stderr: "#]]);
+    check_cli(&["-D", "1x=2", "-e", "print 1;"], "", expect![[r#"
+        status: Some(2)
+        stdout: 
+        stderr: Invalid variable name: "1x"
+    "#]]);
+    check_cli(&["-D", "=2", "-e", "print 1;"], "", expect![[r#"
+        status: Some(2)
+        stdout: 
+        stderr: Invalid variable name: ""
+    "#]]);
+}
+
+#[test]
+fn exit_status() {
+    check_cli(&["-e", "print 1; exit 3; print 2;"], "", expect![[r#"
+        status: Some(3)
+        stdout: 1
+
+        stderr: "#]]);
+    check_cli(&["-e", "if argc { exit 4; }", "--", "a"], "", expect![[r#"
+        status: Some(4)
+        stdout: 
+        stderr: "#]]);
+    check_cli(&["--check", "-e", "exit 3;"], "", expect![[r#"
+        status: Some(0)
+        stdout: 
+        stderr: "#]]);
+    check_cli(&["-e", "exit 255;"], "", expect![[r#"
+        status: Some(255)
+        stdout: 
+        stderr: "#]]);
+    check_cli(&["-e", "exit 256;"], "", expect![[r#"
+        status: Some(1)
+        stdout: 
+        stderr: <eval>: error[E0016]: Exit code 256 out of range 0 to 255 at 1:6 `256;`
+    "#]]);
+}
+
+#[test]
+fn input() {
+    let quote = "l = input(); while l != null { print '> ' + l; l = input(); }";
+    check_cli(&["-e", quote], "a\nb\r\n\nc", expect![[r#"
+        status: Some(0)
+        stdout: > a
+        > b
+        > 
+        > c
+
+        stderr: "#]]);
+    check_cli(&["-e", "print input();"], "", expect![[r#"
+        status: Some(0)
+        stdout: NULL
+
+        stderr: "#]]);
+}
+
+#[test]
+fn line_mode() {
+    let count = "BEGIN { n = 0; } n = n + -line; END { print n; }";
+    check_cli(&["-n", "-e", count], "ab\ncd\r\ne", expect![[r#"
+        status: Some(0)
+        stdout: 5
+
+        stderr: "#]]);
+    check_cli(&["-p", "-e", "line = line + '|' + nr;"], "a\nb\n", expect![[r#"
+        status: Some(0)
+        stdout: a|1
+        b|2
+
+        stderr: "#]]);
+    check_cli(&["-n", "-p", "-e", "print nr;"], "a\n", expect![[r#"
+        status: Some(0)
+        stdout: 1
+        a
+
+        stderr: "#]]);
+    check_cli(&["-n", "-e", "if nr == 2 { exit 5; } print line;"], "a\nb\nc\n", expect![[r#"
+        status: Some(5)
+        stdout: a
+
+        stderr: "#]]);
+    check_cli(&["-p", "-D", "warnings", "-e", "line = 'x';"], "a\n", expect![[r#"
+        status: Some(0)
+        stdout: x
+
+        stderr: "#]]);
+    check_cli(&["-n", "-D", "warnings", "-e", "line = 'x';"], "a\n", expect![[r#"
+        status: Some(1)
+        stdout: 
+        stderr: <eval>: error[W0001]: Variable `line` is assigned but never read at 1:1 `line = 'x';`
+    "#]]);
+    check_cli(&["-n", "-e", "BEGIN { exit 3; } print line; END { print 'end'; }"], "a\n", expect![[r#"
+        status: Some(3)
+        stdout: 
+        stderr: "#]]);
+    check_cli(&["-n", "--check", "-e", "x = line; END { print x; }"], "a\n", expect![[r#"
+        status: Some(0)
+        stdout: 
+        stderr: <eval>: warning[W0005]: Variable `x` may be used before assignment at 1:23 `x; }`
+    "#]]);
+    check_cli(&["-n", "-"], "print line;", expect![[r#"
+        status: Some(2)
+        stdout: 
+        stderr: Cannot read <prog> from stdin with `-n` or `-p`
+    "#]]);
+}
+
+#[test]
+fn file_access() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("file_access");
+    _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("data")).unwrap();
+    fs::write(dir.join("secret.txt"), "secret").unwrap();
+    let src = "write_file('data/a.txt', 'a'); append_file('data/a.txt', 'b'); \
+               print read_file('data/a.txt'); print exists('data/none');";
+    check_cli_in(&dir, &["-e", src], "", expect![[r#"
+        status: Some(1)
+        stdout: 
+        stderr: <eval>: error[E0014]: File access is not allowed, see `--allow-dir` at 1:1 `write_file('data/a.txt', 'a'); append_file('data/a.txt', 'b'); print read_file('data/a.txt'); print exists('data/none');`
+    "#]]);
+    check_cli_in(&dir, &["--allow-dir", "data", "-e", src], "", expect![[r#"
+        status: Some(0)
+        stdout: ab
+        NULL
+
+        stderr: "#]]);
+    check_cli_in(&dir, &["--allow-dir", "data", "-e", "print read_file('secret.txt');"], "", expect![[r#"
+        status: Some(1)
+        stdout: 
+        stderr: <eval>: error[E0014]: Access denied, "secret.txt" is outside of `--allow-dir` at 1:7 `read_file('secret.txt');`
+    "#]]);
+    check_cli_in(&dir, &["--allow-dir", "data", "-e", "print read_file('data/../secret.txt');"], "", expect![[r#"
+        status: Some(1)
+        stdout: 
+        stderr: <eval>: error[E0014]: Access denied, "data/../secret.txt" is outside of `--allow-dir` at 1:7 `read_file('data/../secret.txt');`
+    "#]]);
+    check_cli_in(&dir, &["--allow-dir", ".", "-e", "print read_file('data/../secret.txt');"], "", expect![[r#"
+        status: Some(0)
+        stdout: secret
+
+        stderr: "#]]);
+    check_cli_in(&dir, &["--allow-dir", "data", "-e", "print read_file('data/none');"], "", expect![[r#"
+        status: Some(1)
+        stdout: 
+        stderr: <eval>: error[E0014]: failed to open file `data/none`: No such file or directory (os error 2) at 1:7 `read_file('data/none');`
+    "#]]);
+    check_cli_in(&dir, &["--allow-dir", "none", "-e", "print 1;"], "", expect![[r#"
+        status: Some(2)
+        stdout: 
+        stderr: failed to canonicalize path `none`: No such file or directory (os error 2)
+    "#]]);
+}
+
+#[test]
+fn clock() {
+    let src = "start = now(); sleep(0.05); print now() > 1700000000; print now() - start >= 0.05;";
+    check_cli(&["-e", src], "", expect![[r#"
+        status: Some(0)
+        stdout: 1
+        1
+
+        stderr: "#]]);
+}
+
+#[test]
+fn seed() {
+    let src = "print random(); print random_int(1, 100);";
+    check_cli(&["--seed", "42", "-e", src], "", expect![[r#"
+        status: Some(0)
+        stdout: 0.7415648787718233
+        92
+
+        stderr: "#]]);
+    check_cli(&["--seed=42", "-e", src], "", expect![[r#"
+        status: Some(0)
+        stdout: 0.7415648787718233
+        92
+
+        stderr: "#]]);
+    check_cli(&["--seed", "-1", "-e", src], "", expect![[r#"
+        status: Some(2)
+        stdout: 
+        stderr: Invalid seed "-1": invalid digit found in string
+    "#]]);
+}
+
+#[test]
+fn write() {
+    check_cli(&["-e", "write 'a'; write 1; print '';"], "", expect![[r#"
+        status: Some(0)
+        stdout: a1
+
+        stderr: "#]]);
+}
+
+#[test]
+fn eprint() {
+    check_cli(&["-e", "print 'data'; eprint 'message'; print 'more';"], "", expect![[r#"
+        status: Some(0)
+        stdout: data
+        more
+
+        stderr: message
+    "#]]);
+}
+
+#[test]
+fn number_format() {
+    let src = "print 1/3; write 1234.5; print ''; eprint 'e' + 2/3; print fmt(2, '03');";
+    check_cli(&["--number-format", ",.2f", "-e", src], "", expect![[r#"
+        status: Some(0)
+        stdout: 0.33
+        1,234.50
+        002
+
+        stderr: e0.6666666666666666
+    "#]]);
+    check_cli(&["--number-format=x", "-e", src], "", expect![[r#"
+        status: Some(2)
+        stdout: 
+        stderr: Unexpected `x` in format spec "x"
+    "#]]);
+    check_cli(&["--number-format=.70000f", "-e", src], "", expect![[r#"
+        status: Some(2)
+        stdout: 
+        stderr: Format spec precision 70000 too large, at most 10000
+    "#]]);
+}
@@ -0,0 +1,111 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use expect_test::{Expect, expect};
+
+/// Run `lsp` subcommand with scripted messages, returns one response per line
+#[track_caller]
+fn check_lsp(messages: &[&str], expect: Expect) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_run-str-demo"))
+        .arg("lsp")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    for msg in messages {
+        write!(stdin, "Content-Length: {}\r\n\r\n{msg}", msg.len()).unwrap();
+    }
+    drop(stdin);
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+
+    let mut stdout = &*String::from_utf8(output.stdout).unwrap();
+    let mut actual = String::new();
+    while !stdout.is_empty() {
+        let (header, rest) = stdout.split_once("\r\n\r\n").unwrap();
+        let len: usize = header.strip_prefix("Content-Length: ").unwrap().parse().unwrap();
+        actual += &rest[..len];
+        actual += "\n";
+        stdout = &rest[len..];
+    }
+    expect.assert_eq(&actual);
+}
+
+const INITIALIZE: &str = r#"{"jsonrpc":"2.0","id":0,"method":"initialize","params":{"capabilities":{}}}"#;
+const INITIALIZED: &str = r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#;
+const SHUTDOWN: &str = r#"{"jsonrpc":"2.0","id":99,"method":"shutdown"}"#;
+const EXIT: &str = r#"{"jsonrpc":"2.0","method":"exit"}"#;
+
+#[test]
+fn initialize() {
+    check_lsp(&[INITIALIZE, INITIALIZED, SHUTDOWN, EXIT], expect![[r#"
+        {"jsonrpc":"2.0","id":0,"result":{"capabilities":{"textDocumentSync":1,"hoverProvider":true,"definitionProvider":true,"documentSymbolProvider":true,"semanticTokensProvider":{"legend":{"tokenTypes":["keyword","variable","number","string","operator","comment"],"tokenModifiers":[]},"full":true}},"serverInfo":{"name":"run-str-demo","version":"0.1.0"}}}
+        {"jsonrpc":"2.0","id":99,"result":null}
+    "#]]);
+}
+
+#[test]
+fn diagnostics() {
+    check_lsp(&[
+        INITIALIZE,
+        r#"{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{"textDocument":{"uri":"file:///a.rsd","languageId":"rsd","version":1,"text":"x = 1;\nif x { y = 2; }\nprint y;\n"}}}"#,
+        r#"{"jsonrpc":"2.0","method":"textDocument/didChange","params":{"textDocument":{"uri":"file:///a.rsd","version":2},"contentChanges":[{"text":"print \"测\" + z;"}]}}"#,
+        r#"{"jsonrpc":"2.0","method":"textDocument/didClose","params":{"textDocument":{"uri":"file:///a.rsd"}}}"#,
+        SHUTDOWN,
+        EXIT,
+    ], expect![[r#"
+        {"jsonrpc":"2.0","id":0,"result":{"capabilities":{"textDocumentSync":1,"hoverProvider":true,"definitionProvider":true,"documentSymbolProvider":true,"semanticTokensProvider":{"legend":{"tokenTypes":["keyword","variable","number","string","operator","comment"],"tokenModifiers":[]},"full":true}},"serverInfo":{"name":"run-str-demo","version":"0.1.0"}}}
+        {"jsonrpc":"2.0","method":"textDocument/publishDiagnostics","params":{"uri":"file:///a.rsd","diagnostics":[{"range":{"start":{"line":2,"character":6},"end":{"line":2,"character":7}},"severity":2,"code":"W0005","source":"run-str-demo","message":"Variable `y` may be used before assignment"}]}}
+        {"jsonrpc":"2.0","method":"textDocument/publishDiagnostics","params":{"uri":"file:///a.rsd","diagnostics":[{"range":{"start":{"line":0,"character":12},"end":{"line":0,"character":13}},"severity":2,"code":"W0006","source":"run-str-demo","message":"Variable `z` is never assigned"}]}}
+        {"jsonrpc":"2.0","method":"textDocument/publishDiagnostics","params":{"uri":"file:///a.rsd","diagnostics":[]}}
+        {"jsonrpc":"2.0","id":99,"result":null}
+    "#]]);
+}
+
+#[test]
+fn navigation() {
+    check_lsp(&[
+        INITIALIZE,
+        r#"{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{"textDocument":{"uri":"file:///a.rsd","languageId":"rsd","version":1,"text":"x = 1;\ns = 'a' + x;\nx = null;\nprint s + x; // c\n"}}}"#,
+        r#"{"jsonrpc":"2.0","id":1,"method":"textDocument/hover","params":{"textDocument":{"uri":"file:///a.rsd"},"position":{"line":3,"character":11}}}"#,
+        r#"{"jsonrpc":"2.0","id":2,"method":"textDocument/hover","params":{"textDocument":{"uri":"file:///a.rsd"},"position":{"line":1,"character":0}}}"#,
+        r#"{"jsonrpc":"2.0","id":3,"method":"textDocument/hover","params":{"textDocument":{"uri":"file:///a.rsd"},"position":{"line":3,"character":1}}}"#,
+        r#"{"jsonrpc":"2.0","id":4,"method":"textDocument/definition","params":{"textDocument":{"uri":"file:///a.rsd"},"position":{"line":3,"character":6}}}"#,
+        r#"{"jsonrpc":"2.0","id":5,"method":"textDocument/documentSymbol","params":{"textDocument":{"uri":"file:///a.rsd"}}}"#,
+        r#"{"jsonrpc":"2.0","id":6,"method":"textDocument/semanticTokens/full","params":{"textDocument":{"uri":"file:///a.rsd"}}}"#,
+        SHUTDOWN,
+        EXIT,
+    ], expect![[r#"
+        {"jsonrpc":"2.0","id":0,"result":{"capabilities":{"textDocumentSync":1,"hoverProvider":true,"definitionProvider":true,"documentSymbolProvider":true,"semanticTokensProvider":{"legend":{"tokenTypes":["keyword","variable","number","string","operator","comment"],"tokenModifiers":[]},"full":true}},"serverInfo":{"name":"run-str-demo","version":"0.1.0"}}}
+        {"jsonrpc":"2.0","method":"textDocument/publishDiagnostics","params":{"uri":"file:///a.rsd","diagnostics":[]}}
+        {"jsonrpc":"2.0","id":1,"result":{"contents":{"kind":"markdown","value":"```rsd\nx: number | null\n```"},"range":{"start":{"line":3,"character":10},"end":{"line":3,"character":11}}}}
+        {"jsonrpc":"2.0","id":2,"result":{"contents":{"kind":"markdown","value":"```rsd\ns: string\n```"},"range":{"start":{"line":1,"character":0},"end":{"line":1,"character":1}}}}
+        {"jsonrpc":"2.0","id":3,"result":null}
+        {"jsonrpc":"2.0","id":4,"result":{"uri":"file:///a.rsd","range":{"start":{"line":1,"character":0},"end":{"line":1,"character":1}}}}
+        {"jsonrpc":"2.0","id":5,"result":[{"name":"x","detail":"number | null","kind":13,"range":{"start":{"line":0,"character":0},"end":{"line":0,"character":1}},"selectionRange":{"start":{"line":0,"character":0},"end":{"line":0,"character":1}}},{"name":"s","detail":"string","kind":13,"range":{"start":{"line":1,"character":0},"end":{"line":1,"character":1}},"selectionRange":{"start":{"line":1,"character":0},"end":{"line":1,"character":1}}}]}
+        {"jsonrpc":"2.0","id":6,"result":{"data":[0,0,1,1,0,0,2,1,4,0,0,2,1,2,0,1,0,1,1,0,0,2,1,4,0,0,2,3,3,0,0,4,1,4,0,0,2,1,1,0,1,0,1,1,0,0,2,1,4,0,0,2,4,0,0,1,0,5,0,0,0,6,1,1,0,0,2,1,4,0,0,2,1,1,0,0,3,4,5,0]}}
+        {"jsonrpc":"2.0","id":99,"result":null}
+    "#]]);
+}
+
+#[test]
+fn invalid_messages() {
+    check_lsp(&[
+        INITIALIZE,
+        r#"{"jsonrpc":"2.0","id":1,"method":"workspace/unknown"}"#,
+        r#"{"jsonrpc":"2.0","method":"$/unknownNotification"}"#,
+        r#"{"jsonrpc":"2.0","id":2,"#,
+        SHUTDOWN,
+        r#"{"jsonrpc":"2.0","id":3,"method":"textDocument/hover"}"#,
+        EXIT,
+    ], expect![[r#"
+        {"jsonrpc":"2.0","id":0,"result":{"capabilities":{"textDocumentSync":1,"hoverProvider":true,"definitionProvider":true,"documentSymbolProvider":true,"semanticTokensProvider":{"legend":{"tokenTypes":["keyword","variable","number","string","operator","comment"],"tokenModifiers":[]},"full":true}},"serverInfo":{"name":"run-str-demo","version":"0.1.0"}}}
+        {"jsonrpc":"2.0","id":1,"error":{"code":-32601,"message":"Method not found: workspace/unknown"}}
+        {"jsonrpc":"2.0","id":null,"error":{"code":-32700,"message":"Expected a string at byte 24"}}
+        {"jsonrpc":"2.0","id":99,"result":null}
+        {"jsonrpc":"2.0","id":3,"error":{"code":-32600,"message":"Server is shutdown"}}
+    "#]]);
+}